@@ -0,0 +1,607 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::str;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::future::{self, FutureExt, TryFutureExt};
+use http_body_util::{BodyExt, Full};
+use hyper::header::{Headers, IfMatch, IfNoneMatch};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use hyper_async::Request;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use tokio::{task, time};
+
+use KintoConfig;
+use error::KintoError;
+use request::{RequestPreparer, KintoRequest, PayloadedEndpoint};
+use resource::Resource;
+use response::ResponseWrapper;
+use retry::{RetryState, RetryStep};
+use bucket::Bucket;
+use utils::{unwrap_collection_records, timestamp_to_etag};
+
+
+/// HTTP client used by the asynchronous API.
+pub type AsyncHttpClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// Future returned by the asynchronous API.
+pub type KintoFuture<T> = Pin<Box<dyn Future<Output = Result<T, KintoError>> + Send>>;
+
+/// Status, headers and body of a server response.
+type RawResponse = (StatusCode, Headers, Bytes);
+
+
+/// Build an HTTP client with TLS support for the asynchronous API.
+pub fn build_async_http_client() -> AsyncHttpClient {
+    Client::builder(TokioExecutor::new()).build(HttpsConnector::new())
+}
+
+
+/// Convert a prepared request into an asynchronous HTTP request.
+fn build_request(preparer: &RequestPreparer) -> Result<Request<Full<Bytes>>, KintoError> {
+    let mut builder = Request::builder()
+        .method(preparer.method.to_string().as_str())
+        .uri(preparer.full_url().as_str());
+    for header in preparer.full_headers().iter() {
+        builder = builder.header(header.name(), header.value_string().as_str());
+    }
+    Ok(try!(builder.body(Full::new(Bytes::from(preparer.payload())))))
+}
+
+
+/// Send a prepared request once and read the whole response.
+fn send_once(preparer: &RequestPreparer) -> KintoFuture<RawResponse> {
+    let request = match build_request(preparer) {
+        Ok(request) => request,
+        Err(err) => return Box::pin(future::err(err)),
+    };
+
+    let response = preparer
+        .config
+        .async_http_client()
+        .request(request)
+        .map_err(KintoError::from)
+        .and_then(|response| {
+            let status = StatusCode::from_u16(response.status().as_u16());
+
+            let mut headers = Headers::new();
+            for (name, value) in response.headers() {
+                headers.append_raw(name.as_str().to_owned(), value.as_bytes().to_vec());
+            }
+
+            response
+                .into_body()
+                .collect()
+                .map_err(KintoError::from)
+                .map_ok(move |body| (status, headers, body.to_bytes()))
+        });
+
+    Box::pin(response)
+}
+
+
+/// Wait for the given delay, without using the timer if there is none.
+fn sleep(delay: Option<Duration>) -> KintoFuture<()> {
+    match delay {
+        Some(delay) => Box::pin(time::sleep(delay).map(Ok)),
+        None => Box::pin(future::ok(())),
+    }
+}
+
+
+/// Send a prepared request with the backoff, retry and credentials refresh
+/// steps of the blocking `send_prepared`.
+pub fn send_prepared_async(preparer: RequestPreparer) -> KintoFuture<ResponseWrapper> {
+    let retry = RetryState::new(preparer.config.retry_policy.clone(),
+                                preparer.method.to_owned());
+    send_with_retry(preparer, retry)
+}
+
+
+/// Send a prepared request until the retry policy is exhausted.
+fn send_with_retry(preparer: RequestPreparer,
+                   mut retry: RetryState)
+                   -> KintoFuture<ResponseWrapper> {
+    let backoff = preparer.config.backoff();
+    let wait = if preparer.config.retry_policy.respect_backoff {
+        backoff.remaining()
+    } else {
+        None
+    };
+
+    let response = sleep(wait).and_then(move |_| {
+        send_once(&preparer).then(move |response| -> KintoFuture<ResponseWrapper> {
+            let (status, headers, body) = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    return match retry.after_error() {
+                        RetryStep::Retry(delay) => resend(preparer, retry, delay),
+                        _ => Box::pin(future::err(err)),
+                    };
+                }
+            };
+
+            backoff.update(&headers);
+
+            match retry.after_response(status, &headers) {
+                RetryStep::RefreshAuth => {
+                    refresh_and_resend(preparer, retry, (status, headers, body))
+                }
+                RetryStep::Retry(delay) => resend(preparer, retry, delay),
+                RetryStep::Done => finish(preparer, (status, headers, body)),
+            }
+        })
+    });
+
+    Box::pin(response)
+}
+
+
+/// Send the request once again after the given delay.
+fn resend(preparer: RequestPreparer,
+          retry: RetryState,
+          delay: Duration)
+          -> KintoFuture<ResponseWrapper> {
+    Box::pin(sleep(Some(delay)).and_then(move |_| send_with_retry(preparer, retry)))
+}
+
+
+/// Refresh the credentials after a `401 Unauthorized` response, and send
+/// the request once again if they changed.
+fn refresh_and_resend(preparer: RequestPreparer,
+                      mut retry: RetryState,
+                      response: RawResponse)
+                      -> KintoFuture<ResponseWrapper> {
+    // Refreshers may block (e.g. an OAuth token request).
+    let refreshing = preparer.clone();
+    let refreshed = task::spawn_blocking(move || refreshing.refresh_auth());
+
+    Box::pin(refreshed.then(move |refreshed| -> KintoFuture<ResponseWrapper> {
+        if refreshed.unwrap_or(false) {
+            return send_with_retry(preparer, retry);
+        }
+        match retry.after_response(response.0, &response.1) {
+            RetryStep::Retry(delay) => resend(preparer, retry, delay),
+            _ => finish(preparer, response),
+        }
+    }))
+}
+
+
+/// Wrap the final response of a request.
+fn finish(preparer: RequestPreparer,
+          (status, headers, body): RawResponse)
+          -> KintoFuture<ResponseWrapper> {
+    let wrapper = str::from_utf8(&body)
+        .map_err(KintoError::from)
+        .and_then(|serialized| {
+                      ResponseWrapper::from_parts(preparer.config,
+                                                  preparer.path,
+                                                  status,
+                                                  headers,
+                                                  serialized)
+                  });
+    Box::pin(future::ready(wrapper))
+}
+
+
+/// Send the requests of the following pages and join their data.
+fn follow_pages<R>(request: R,
+                   mut base_response: ResponseWrapper,
+                   current_response: &ResponseWrapper)
+                   -> KintoFuture<ResponseWrapper>
+    where R: AsyncKintoRequest
+{
+    let next_page_path = match current_response.next_page_path() {
+        Ok(Some(path)) => path,
+        Ok(None) => return Box::pin(future::ok(base_response)),
+        Err(err) => return Box::pin(future::err(err)),
+    };
+
+    // Repeated request on the provided endpoint
+    let mut temp_request = request.clone();
+    temp_request.preparer().path = next_page_path;
+    temp_request.preparer().query = "".to_owned();
+
+    Box::pin(temp_request.send_async().and_then(move |current_response| {
+        // Join data fields
+        if let Some(new_data) = current_response.body["data"].as_array() {
+            let base_data = base_response.body["data"].as_array_mut().unwrap();
+            base_data.extend(new_data.iter().cloned());
+        }
+        follow_pages(request, base_response, &current_response)
+    }))
+}
+
+
+/// Asynchronous variant of `KintoRequest`, available on every request.
+///
+/// Futures must be driven by a tokio runtime, with its timer enabled to
+/// respect the server backoff and the retry policy of the config.
+pub trait AsyncKintoRequest: KintoRequest + Send + 'static {
+    /// Send the request.
+    fn send_async(&mut self) -> KintoFuture<ResponseWrapper> {
+        self.send_future()
+    }
+
+    /// Send the request and join the data of every following page.
+    fn follow_subrequests_async(&mut self) -> KintoFuture<ResponseWrapper> {
+        let request = self.clone();
+        Box::pin(self.send_async().and_then(move |base_response| {
+            let current_response = base_response.clone();
+            follow_pages(request, base_response, &current_response)
+        }))
+    }
+}
+
+impl<T> AsyncKintoRequest for T where T: KintoRequest + Send + 'static {}
+
+
+/// Send a request and update a copy of the resource with the response.
+fn send_and_unwrap<T, R>(resource: T, request: Result<R, KintoError>) -> KintoFuture<T>
+    where T: Resource + Send + 'static,
+          R: AsyncKintoRequest
+{
+    let mut request = match request {
+        Ok(request) => request,
+        Err(err) => return Box::pin(future::err(err)),
+    };
+
+    let mut resource = resource;
+    Box::pin(request.send_async().map_ok(move |wrapper| {
+                                              resource.unwrap_response(wrapper);
+                                              resource
+                                          }))
+}
+
+
+/// Asynchronous variant of `Resource`, available on every resource.
+///
+/// As futures can not borrow the resource, each method resolves to an
+/// updated copy of it.
+pub trait AsyncResource: Resource + Send + 'static {
+    /// Load the object by id if exists.
+    fn load_async(&self) -> KintoFuture<Self> {
+        send_and_unwrap(self.clone(), self.load_request())
+    }
+
+    /// Set the object on the server (create or update).
+    fn set_async(&self) -> KintoFuture<Self> {
+        if self.get_id().is_none() {
+            return self.create_async();
+        }
+
+        let body = self.get_body();
+        let request = self.update_request()
+            .map(|mut request| {
+                     request.body(body.into());
+                     request
+                 });
+        send_and_unwrap(self.clone(), request)
+    }
+
+    /// Create the object if it does not exist.
+    fn create_async(&self) -> KintoFuture<Self> {
+        let body = self.get_body();
+        let request = self.create_request()
+            .map(|mut request| {
+                     request.body(body.into()).if_none_match(IfNoneMatch::Any);
+                     request
+                 });
+        send_and_unwrap(self.clone(), request)
+    }
+
+    /// Update the existing object.
+    fn update_async(&self) -> KintoFuture<Self> {
+        let if_match = match self.get_timestamp() {
+            Some(stamp) => IfMatch::Items(timestamp_to_etag(stamp)),
+            None => IfMatch::Any,
+        };
+
+        let body = self.get_body();
+        let request = self.update_request()
+            .map(|mut request| {
+                     request.body(body.into()).if_match(if_match);
+                     request
+                 });
+        send_and_unwrap(self.clone(), request)
+    }
+
+    /// Delete the object from the server.
+    fn delete_async(&self) -> KintoFuture<Self> {
+        send_and_unwrap(self.clone(), self.delete_request())
+    }
+}
+
+impl<T> AsyncResource for T where T: Resource + Send + 'static {}
+
+
+/// Asynchronous client for the Kinto HTTP API.
+#[derive(Debug, Clone)]
+pub struct AsyncKintoClient {
+    config: KintoConfig,
+}
+
+
+impl AsyncKintoClient {
+    /// Create a client.
+    pub fn new(config: KintoConfig) -> AsyncKintoClient {
+        AsyncKintoClient { config }
+    }
+
+    /// Select an existing bucket.
+    pub fn bucket(&self, id: &str) -> Bucket {
+        Bucket::new_by_id(self.config.clone(), id)
+    }
+
+    /// Create a new empty bucket with a generated id.
+    pub fn new_bucket(&self) -> Bucket {
+        Bucket::new(self.config.clone())
+    }
+
+    /// List all available buckets.
+    pub fn list_buckets(&self) -> KintoFuture<Vec<Bucket>> {
+        let bucket = self.new_bucket();
+        let mut request = match bucket.list_request() {
+            Ok(request) => request,
+            Err(err) => return Box::pin(future::err(err)),
+        };
+        Box::pin(request
+                     .follow_subrequests_async()
+                     .map_ok(move |response| {
+                                 unwrap_collection_records(&response, &bucket)
+                             }))
+    }
+
+    /// Delete all available buckets.
+    pub fn delete_buckets(&self) -> KintoFuture<()> {
+        let mut request = match self.new_bucket().delete_all_request() {
+            Ok(request) => request,
+            Err(err) => return Box::pin(future::err(err)),
+        };
+        Box::pin(request.follow_subrequests_async().map_ok(|_| ()))
+    }
+
+    /// Flush the server (if the flush endpoint is enabled).
+    pub fn flush(&self) -> KintoFuture<()> {
+        let mut preparer = RequestPreparer::new(self.config.clone(), "/__flush__".to_owned());
+        preparer.method = Method::Post;
+
+        Box::pin(send_once(&preparer).map_ok(|_| ()))
+    }
+}
+
+
+#[cfg(test)]
+mod test_async_client {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::str;
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    use KintoConfig;
+    use async_client::{AsyncKintoClient, AsyncKintoRequest, AsyncResource};
+    use auth::TokenAuth;
+    use batch::{BatchRequest, BatchResponseWrapper};
+    use cache::MemoryCache;
+    use error::KintoError;
+    use resource::Resource;
+    use retry::RetryPolicy;
+    use utils::tests::setup_config;
+
+    fn setup_async_client(runtime: &Runtime) -> AsyncKintoClient {
+        let client = AsyncKintoClient::new(setup_config());
+        runtime.block_on(client.flush()).unwrap();
+        client
+    }
+
+    /// Whether the headers and the whole body of a request were received.
+    fn request_is_complete(request: &str) -> bool {
+        let end = match request.find("\r\n\r\n") {
+            Some(end) => end,
+            None => return false,
+        };
+        let length = request[..end]
+            .lines()
+            .filter_map(|line| {
+                            let line = line.to_lowercase();
+                            let value = line.trim_start_matches("content-length:");
+                            value.trim().parse().ok()
+                        })
+            .next()
+            .unwrap_or(0);
+        request.len() >= end + 4 + length
+    }
+
+    /// Serve the given responses, one per connection, and return the server
+    /// URL and the received requests.
+    ///
+    /// Each response is a status, followed by the body or by headers lines
+    /// and the body, e.g. `"200\r\nETag: \"42\"\r\n\r\n{}"`.
+    fn serve(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for (status, rest) in responses.iter().map(|r| r.split_at(3)) {
+                let (headers, body) = match rest.find("\r\n\r\n") {
+                    Some(end) => (&rest[2..end + 2], &rest[end + 4..]),
+                    None => ("", rest),
+                };
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut buffer = [0; 1024];
+                while !request_is_complete(&request) {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.push_str(str::from_utf8(&buffer[..read]).unwrap());
+                }
+                requests.push(request);
+                write!(stream,
+                       "HTTP/1.1 {} -\r\n{}Content-Length: {}\r\n\
+                        Connection: close\r\n\r\n{}",
+                       status,
+                       headers,
+                       body.len(),
+                       body)
+                        .unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_retry_unavailable_server() {
+        let (url, server) = serve(vec!["503{}", r#"200{"data": {"id": "food"}}"#]);
+        let mut config = KintoConfig::new(url, None);
+        config.retry_policy = RetryPolicy::with_retries(2);
+        config.retry_policy.base_delay = Duration::from_millis(1);
+
+        let runtime = Runtime::new().unwrap();
+        let client = AsyncKintoClient::new(config);
+        let bucket = runtime.block_on(client.bucket("food").load_async()).unwrap();
+        assert_eq!(bucket.data.unwrap()["id"], "food");
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_refresh_expired_credentials() {
+        let (url, server) = serve(vec!["401{}", r#"200{"data": {"id": "food"}}"#]);
+        let auth = TokenAuth::bearer("expired")
+            .with_refresher(Box::new(|| Some("fresh".to_owned())));
+        let config = KintoConfig::with_auth(url, auth);
+
+        let runtime = Runtime::new().unwrap();
+        let client = AsyncKintoClient::new(config);
+        let bucket = runtime.block_on(client.bucket("food").load_async()).unwrap();
+        assert_eq!(bucket.data.unwrap()["id"], "food");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("Bearer expired"));
+        assert!(requests[1].contains("Bearer fresh"));
+    }
+
+    #[test]
+    fn test_batch_is_split_in_chunks() {
+        let first = r#"200{"responses": [{"status": 200, "path": "/v1/buckets/a"}]}"#;
+        let second = r#"200{"responses": [{"status": 200, "path": "/v1/buckets/b"}]}"#;
+        let (url, server) = serve(vec![first, second]);
+        let client = AsyncKintoClient::new(KintoConfig::new(url, None));
+        let mut batch = BatchRequest::new(client.config.clone());
+        batch.max_requests = 1;
+        batch.add_request(client.bucket("a").load_request().unwrap());
+        batch.add_request(client.bucket("b").load_request().unwrap());
+
+        let runtime = Runtime::new().unwrap();
+        let response = runtime.block_on(batch.send_async()).unwrap();
+        let result = BatchResponseWrapper::from_response(response, &batch.requests);
+        let result = result.unwrap();
+        assert_eq!(result.responses[0].path, "/buckets/a");
+        assert_eq!(result.responses[1].path, "/buckets/b");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("/buckets/a"));
+        assert!(!requests[0].contains("/buckets/b"));
+        assert!(requests[1].contains("/buckets/b"));
+    }
+
+    #[test]
+    fn test_batch_chunk_failure() {
+        let first = r#"200{"responses": [{"status": 200, "path": "/v1/buckets/a"}]}"#;
+        let second = r#"400{"errno": 107, "message": "Invalid request"}"#;
+        let (url, server) = serve(vec![first, second]);
+        let client = AsyncKintoClient::new(KintoConfig::new(url, None));
+        let mut batch = BatchRequest::new(client.config.clone());
+        batch.max_requests = 1;
+        batch.add_request(client.bucket("a").load_request().unwrap());
+        batch.add_request(client.bucket("b").load_request().unwrap());
+
+        let runtime = Runtime::new().unwrap();
+        match runtime.block_on(batch.send_async()).unwrap_err() {
+            KintoError::PartialBatchError { response, error } => {
+                let result = BatchResponseWrapper::from_response(*response,
+                                                                 &batch.requests)
+                        .unwrap();
+                assert_eq!(result.responses.len(), 1);
+                assert_eq!(error.response().unwrap().errno, 107);
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reads_are_revalidated() {
+        let first = "200\r\nETag: \"42\"\r\n\r\n{\"data\": {\"id\": \"food\"}}";
+        let (url, server) = serve(vec![first, "304\r\nETag: \"42\"\r\n\r\n"]);
+        let config = KintoConfig::new(url, None).with_cache(MemoryCache::new());
+        let client = AsyncKintoClient::new(config);
+
+        let runtime = Runtime::new().unwrap();
+        let bucket = runtime.block_on(client.bucket("food").load_async()).unwrap();
+        assert_eq!(bucket.data.unwrap()["id"], "food");
+        let bucket = runtime.block_on(client.bucket("food").load_async()).unwrap();
+        assert_eq!(bucket.data.unwrap()["id"], "food");
+
+        let requests: Vec<String> = server
+            .join()
+            .unwrap()
+            .iter()
+            .map(|request| request.to_lowercase())
+            .collect();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"42\""));
+    }
+
+    #[test]
+    fn test_create_and_load_bucket() {
+        let runtime = Runtime::new().unwrap();
+        let client = setup_async_client(&runtime);
+
+        let mut bucket = client.bucket("food");
+        bucket.data = json!({"good": true}).into();
+        let created = runtime.block_on(bucket.create_async()).unwrap();
+        assert_eq!(created.data.unwrap()["good"], true);
+
+        let loaded = runtime.block_on(client.bucket("food").load_async()).unwrap();
+        assert_eq!(loaded.data.unwrap()["id"], "food");
+    }
+
+    #[test]
+    fn test_update_and_delete_bucket() {
+        let runtime = Runtime::new().unwrap();
+        let client = setup_async_client(&runtime);
+
+        let bucket = runtime.block_on(client.bucket("food").set_async()).unwrap();
+        let updated = runtime.block_on(bucket.update_async()).unwrap();
+        assert_eq!(updated.data.unwrap()["id"], "food");
+
+        runtime.block_on(client.bucket("food").delete_async()).unwrap();
+        runtime
+            .block_on(client.bucket("food").load_async())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_list_buckets() {
+        let runtime = Runtime::new().unwrap();
+        let client = setup_async_client(&runtime);
+
+        for _ in 0..3 {
+            runtime.block_on(client.new_bucket().create_async()).unwrap();
+        }
+        let buckets = runtime.block_on(client.list_buckets()).unwrap();
+        assert_eq!(buckets.len(), 3);
+
+        runtime.block_on(client.delete_buckets()).unwrap();
+        let buckets = runtime.block_on(client.list_buckets()).unwrap();
+        assert_eq!(buckets.len(), 0);
+    }
+}
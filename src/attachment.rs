@@ -0,0 +1,146 @@
+use std::io::Read;
+
+use serde_json;
+use serde_json::Value;
+
+use KintoConfig;
+use error::KintoError;
+use server_info::ServerInfo;
+use utils::sha256_hex;
+
+
+/// File attached to a record by the kinto-attachment plugin.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(default)]
+    pub filename: String,
+    /// Location of the file, relative to the server `base_url` capability.
+    pub location: String,
+    /// Hex encoded SHA-256 of the file.
+    pub hash: String,
+    pub size: u64,
+    #[serde(default)]
+    pub mimetype: String,
+}
+
+
+impl Attachment {
+    /// Read the attachment from the record data, if any.
+    pub fn from_data(data: &Value) -> Option<Attachment> {
+        if !data["attachment"].is_object() {
+            return None;
+        }
+        serde_json::from_value(data["attachment"].to_owned()).ok()
+    }
+
+    /// Get the absolute url of the file.
+    ///
+    /// The files base url is read from the `attachments` server capability.
+    pub fn url(&self, config: &KintoConfig) -> Result<String, KintoError> {
+        if self.location.starts_with("http://") || self.location.starts_with("https://") {
+            return Ok(self.location.to_owned());
+        }
+
+        let info = try!(ServerInfo::fetch(config.clone()));
+        let base_url = match info.capability("attachments") {
+            Some(capability) => capability["base_url"].as_str().map(|s| s.to_owned()),
+            None => None,
+        };
+        match base_url {
+            Some(base_url) => {
+                Ok(format!("{}/{}",
+                           base_url.trim_end_matches('/'),
+                           self.location.trim_start_matches('/')))
+            }
+            None => Err(KintoError::UnavailableEndpointError),
+        }
+    }
+
+    /// Download the file and check its size and hash.
+    ///
+    /// No credentials are sent, as files are usually served by another host.
+    pub fn download(&self, config: &KintoConfig) -> Result<Vec<u8>, KintoError> {
+        let url = try!(self.url(config));
+        let mut response = try!(config.http_client().get(&url).send());
+
+        let mut content = vec![];
+        try!(response.read_to_end(&mut content));
+        if !response.status.is_success() {
+            let body = String::from_utf8_lossy(&content);
+            return Err(KintoError::from_response(response.status, &body));
+        }
+
+        try!(self.verify(&content));
+        Ok(content)
+    }
+
+    /// Check that the content matches the attachment size and hash.
+    pub fn verify(&self, content: &[u8]) -> Result<(), KintoError> {
+        if content.len() as u64 != self.size {
+            let message = format!("attachment size is {} instead of {}",
+                                  content.len(),
+                                  self.size);
+            return Err(KintoError::InvalidResponseError(message));
+        }
+
+        let hash = sha256_hex(content);
+        if !hash.eq_ignore_ascii_case(&self.hash) {
+            let message = format!("attachment hash is {} instead of {}", hash, self.hash);
+            return Err(KintoError::InvalidResponseError(message));
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test_attachment {
+    use attachment::Attachment;
+    use utils::sha256_hex;
+    use utils::tests::setup_config;
+
+    fn attachment() -> Attachment {
+        Attachment {
+            filename: "hello.txt".to_owned(),
+            location: "https://cdn.example.com/files/hello.txt".to_owned(),
+            hash: "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+                .to_owned(),
+            size: 5,
+            mimetype: "text/plain".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(sha256_hex(b"hello"), attachment().hash);
+    }
+
+    #[test]
+    fn test_attachment_from_data() {
+        let data = json!({
+            "id": "a",
+            "attachment": {
+                "filename": "hello.txt",
+                "location": "https://cdn.example.com/files/hello.txt",
+                "hash": attachment().hash,
+                "size": 5,
+                "mimetype": "text/plain",
+            }
+        });
+        assert_eq!(Attachment::from_data(&data), Some(attachment()));
+        assert_eq!(Attachment::from_data(&json!({"id": "a"})), None);
+    }
+
+    #[test]
+    fn test_absolute_location_url() {
+        let url = attachment().url(&setup_config()).unwrap();
+        assert_eq!(url, "https://cdn.example.com/files/hello.txt");
+    }
+
+    #[test]
+    fn test_verify_content() {
+        attachment().verify(b"hello").unwrap();
+        attachment().verify(b"hellO").unwrap_err();
+        attachment().verify(b"hello!").unwrap_err();
+    }
+}
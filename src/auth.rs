@@ -0,0 +1,155 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::RwLock;
+
+use hyper::header::{Headers, Authorization, Scheme};
+
+
+/// Authentication method used to sign requests.
+///
+/// Implemented for every hyper `Authorization` scheme (e.g. `Basic`, used by
+/// the Kinto accounts plugin, or `Bearer` for OpenID Connect tokens).
+pub trait Auth: fmt::Debug + Send + Sync {
+    /// Set the authentication headers of a request.
+    fn apply(&self, headers: &mut Headers);
+
+    /// Refresh expired credentials after a `401 Unauthorized` response.
+    ///
+    /// Return `true` if the credentials changed, in which case the request
+    /// is sent once again.
+    fn refresh(&self) -> bool {
+        false
+    }
+}
+
+
+impl<S> Auth for Authorization<S>
+    where S: Scheme + Any
+{
+    fn apply(&self, headers: &mut Headers) {
+        headers.set(self.clone());
+    }
+}
+
+
+/// Function returning a new token, or `None` if it can not be refreshed.
+pub type TokenRefresher = Box<dyn Fn() -> Option<String> + Send + Sync>;
+
+
+/// Token sent with a custom scheme (e.g. `Authorization: Portier <token>`),
+/// which can be refreshed when it expires.
+pub struct TokenAuth {
+    scheme: String,
+    token: RwLock<String>,
+    refresher: Option<TokenRefresher>,
+}
+
+
+impl TokenAuth {
+    /// Create a token authentication for the given scheme.
+    pub fn new(scheme: &str, token: &str) -> TokenAuth {
+        TokenAuth {
+            scheme: scheme.to_owned(),
+            token: RwLock::new(token.to_owned()),
+            refresher: None,
+        }
+    }
+
+    /// Create a `Bearer` token authentication (e.g. OpenID Connect).
+    pub fn bearer(token: &str) -> TokenAuth {
+        TokenAuth::new("Bearer", token)
+    }
+
+    /// Set the function used to get a new token on `401 Unauthorized`.
+    pub fn with_refresher(mut self, refresher: TokenRefresher) -> TokenAuth {
+        self.refresher = Some(refresher);
+        self
+    }
+
+    /// Get the current token.
+    pub fn token(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+}
+
+
+impl fmt::Debug for TokenAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never leak the token in logs.
+        f.debug_struct("TokenAuth")
+            .field("scheme", &self.scheme)
+            .field("refreshable", &self.refresher.is_some())
+            .finish()
+    }
+}
+
+
+impl Auth for TokenAuth {
+    fn apply(&self, headers: &mut Headers) {
+        let value = format!("{} {}", self.scheme, self.token());
+        headers.set_raw("Authorization", vec![value.into_bytes()]);
+    }
+
+    fn refresh(&self) -> bool {
+        let refresher = match self.refresher {
+            Some(ref refresher) => refresher,
+            None => return false,
+        };
+        match refresher() {
+            Some(token) => {
+                *self.token.write().unwrap() = token;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_auth {
+    use std::str;
+
+    use hyper::header::{Headers, Authorization, Basic, Bearer};
+
+    use auth::{Auth, TokenAuth};
+
+    fn authorization(auth: &dyn Auth) -> String {
+        let mut headers = Headers::new();
+        auth.apply(&mut headers);
+        let raw = headers.get_raw("Authorization").unwrap();
+        str::from_utf8(&raw[0]).unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let auth = Authorization(Basic {
+                                     username: "a".to_owned(),
+                                     password: Some("a".to_owned()),
+                                 });
+        assert_eq!(authorization(&auth), "Basic YTph");
+    }
+
+    #[test]
+    fn test_bearer_auth() {
+        let auth = Authorization(Bearer { token: "abc".to_owned() });
+        assert_eq!(authorization(&auth), "Bearer abc");
+        assert!(!auth.refresh());
+    }
+
+    #[test]
+    fn test_custom_scheme() {
+        let auth = TokenAuth::new("Portier", "abc");
+        assert_eq!(authorization(&auth), "Portier abc");
+        assert!(!auth.refresh());
+    }
+
+    #[test]
+    fn test_refresh_token() {
+        let refresher = Box::new(|| Some("s3cr3t".to_owned()));
+        let auth = TokenAuth::bearer("expired").with_refresher(refresher);
+        assert!(auth.refresh());
+        assert_eq!(authorization(&auth), "Bearer s3cr3t");
+        assert!(!format!("{:?}", auth).contains("s3cr3t"));
+    }
+}
@@ -1,18 +1,42 @@
+use std::cmp;
+#[cfg(feature = "async")]
+use std::vec;
+
+#[cfg(feature = "async")]
+use futures::future::{self, FutureExt};
 use hyper::method::Method;
-use hyper::header::{Headers, ContentType};
+use hyper::header::{Headers, ContentType, IfMatch, IfNoneMatch};
 use hyper::status::StatusCode;
+use serde_json::Value;
 use serde_json::map::Map;
 
 use KintoConfig;
+use error::KintoError;
 use paths::Paths;
-use request::{RequestPreparer, KintoRequest};
+use request::{RequestPreparer, KintoRequest, PayloadedEndpoint, send_prepared};
+use resource::Resource;
 use response::ResponseWrapper;
+use server_info::ServerInfo;
+use collection::Collection;
+use record::Record;
+use url::Url;
+use utils::timestamp_to_etag;
+#[cfg(feature = "async")]
+use async_client::{KintoFuture, send_prepared_async};
+
+
+/// Default maximum number of requests per batch on Kinto servers.
+pub const DEFAULT_BATCH_MAX_REQUESTS: usize = 25;
 
 
 #[derive(Debug, Clone)]
 pub struct BatchRequest {
     pub preparer: RequestPreparer,
     pub requests: Vec<RequestPreparer>,
+    /// Maximum number of requests sent in a single `/batch` call.
+    pub max_requests: usize,
+    /// Values shared by every request (e.g. method, path or headers).
+    pub defaults: Map<String, Value>,
 }
 
 
@@ -24,47 +48,188 @@ impl BatchRequest {
         BatchRequest {
             preparer: preparer,
             requests: vec![],
+            max_requests: DEFAULT_BATCH_MAX_REQUESTS,
+            defaults: Map::new(),
         }
     }
 
+    /// Set the default method of the requests, which is then omitted from
+    /// the requests using it.
+    pub fn default_method(&mut self, method: Method) -> &mut Self {
+        self.defaults.insert("method".to_owned(), method.to_string().into());
+        self
+    }
+
+    /// Set the default path of the requests, which is then omitted from the
+    /// requests using it.
+    pub fn default_path(&mut self, path: &str) -> &mut Self {
+        self.defaults.insert("path".to_owned(), path.into());
+        self
+    }
+
+    /// Set a default header of the requests.
+    pub fn default_header(&mut self, name: &str, value: &str) -> &mut Self {
+        let headers = self.defaults
+            .entry("headers".to_owned())
+            .or_insert_with(|| json!({}));
+        headers[name] = value.into();
+        self
+    }
+
+    /// Use the `batch_max_requests` limit advertised by the server.
+    pub fn limit_from(&mut self, info: &ServerInfo) -> &mut Self {
+        if let Some(max_requests) = info.settings.batch_max_requests {
+            self.max_requests = max_requests;
+        }
+        self
+    }
+
     pub fn add_request<T>(&mut self, mut entry: T)
         where T: KintoRequest
     {
         self.requests.push(entry.preparer().clone());
     }
-}
 
+    /// Send the requests and match each subresponse with its request.
+    pub fn send_batch(&mut self) -> Result<BatchResponseWrapper, KintoError> {
+        let response = try!(self.send());
+        BatchResponseWrapper::from_response(response, &self.requests)
+    }
 
-impl KintoRequest for BatchRequest {
-    fn preparer(&mut self) -> &mut RequestPreparer {
+    /// Build the `/batch` request of each chunk of `max_requests` requests.
+    fn chunk_preparers(&self) -> Vec<RequestPreparer> {
+        self.requests
+            .chunks(cmp::max(self.max_requests, 1))
+            .map(|chunk| {
+                     let mut preparer = self.preparer.clone();
+                     preparer.body = self.batch_body(chunk).into();
+                     preparer
+                 })
+            .collect()
+    }
 
+    /// Build the `/batch` body for the given requests.
+    fn batch_body(&self, requests: &[RequestPreparer]) -> Value {
         let mut json_requests = vec![];
 
-        for req in self.requests.clone() {
+        for req in requests.iter().cloned() {
 
             let mut headers = Map::new();
             for header in req.headers.iter() {
-                headers.insert(header.name().to_owned(), header.to_string().into());
+                headers.insert(header.name().to_owned(), header.value_string().into());
             }
 
-            let entry = json!({
-                "method": req.method.to_string(),
-                "path": req.path,
-                "body": req.body.unwrap_or(json!({})),
+            let mut entry = json!({
                 "headers": headers,
             });
+
+            // Method and path are left to the defaults when they match them
+            let method = Value::from(req.method.to_string());
+            if self.defaults.get("method") != Some(&method) {
+                entry["method"] = method;
+            }
+            let path = Value::from(req.path);
+            if self.defaults.get("path") != Some(&path) {
+                entry["path"] = path;
+            }
+
+            // Only payloaded requests (POST, PUT, PATCH) carry a body
+            if let Some(body) = req.body {
+                entry["body"] = body;
+            }
             json_requests.push(entry);
 
         }
 
-        let body = json!({
+        let mut body = json!({
             "requests": json_requests
         });
+        if !self.defaults.is_empty() {
+            body["defaults"] = Value::Object(self.defaults.clone());
+        }
+        body
+    }
+}
 
-        self.preparer.body = body.into();
 
+impl KintoRequest for BatchRequest {
+    fn preparer(&mut self) -> &mut RequestPreparer {
+        self.preparer.body = self.batch_body(&self.requests).into();
         &mut self.preparer
     }
+
+    /// Send the requests in chunks of `max_requests`, merging the
+    /// subresponses in order. The status and headers are the ones of the
+    /// first chunk.
+    ///
+    /// If a chunk fails after others were sent, the merged response of the
+    /// committed ones is returned in a `KintoError::PartialBatchError`.
+    fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
+        if self.requests.len() <= self.max_requests {
+            return send_prepared(self.preparer());
+        }
+
+        let mut merged: Option<ResponseWrapper> = None;
+        for preparer in self.chunk_preparers() {
+            merged = Some(try!(merge_chunk(merged, send_prepared(&preparer))));
+        }
+        Ok(merged.unwrap())
+    }
+
+    /// Send the requests in chunks with the asynchronous client, like `send`.
+    #[cfg(feature = "async")]
+    fn send_future(&mut self) -> KintoFuture<ResponseWrapper> {
+        if self.requests.len() <= self.max_requests {
+            return send_prepared_async(self.preparer().clone());
+        }
+        send_chunks_async(self.chunk_preparers().into_iter(), None)
+    }
+}
+
+
+/// Merge the response of a chunk into the responses of the previous ones.
+///
+/// A failure after some chunks were committed is returned as a
+/// `KintoError::PartialBatchError`.
+fn merge_chunk(merged: Option<ResponseWrapper>,
+               response: Result<ResponseWrapper, KintoError>)
+               -> Result<ResponseWrapper, KintoError> {
+    match (merged, response) {
+        (None, response) => response,
+        (Some(merged), Err(err)) => {
+            Err(KintoError::PartialBatchError {
+                    response: Box::new(merged),
+                    error: Box::new(err),
+                })
+        }
+        (Some(mut merged), Ok(response)) => {
+            if let Some(responses) = response.body["responses"].as_array() {
+                merged.body["responses"]
+                    .as_array_mut()
+                    .unwrap()
+                    .extend(responses.iter().cloned());
+            }
+            Ok(merged)
+        }
+    }
+}
+
+
+/// Send the remaining chunks one after the other.
+#[cfg(feature = "async")]
+fn send_chunks_async(mut chunks: vec::IntoIter<RequestPreparer>,
+                     merged: Option<ResponseWrapper>)
+                     -> KintoFuture<ResponseWrapper> {
+    let preparer = match chunks.next() {
+        Some(preparer) => preparer,
+        None => return Box::pin(future::ok(merged.unwrap())),
+    };
+    Box::pin(send_prepared_async(preparer).then(move |response| {
+        match merge_chunk(merged, response) {
+            Ok(merged) => send_chunks_async(chunks, Some(merged)),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }))
 }
 
 
@@ -74,47 +239,268 @@ pub struct BatchResponseWrapper {
     pub status: StatusCode,
     pub headers: Headers,
     pub responses: Vec<ResponseWrapper>,
+    /// Request matching each response.
+    pub requests: Vec<RequestPreparer>,
+}
+
+
+/// Get the path prefix (e.g. "/v1") of the server url.
+fn server_path_prefix(server_url: &str) -> String {
+    match Url::parse(server_url) {
+        Ok(url) => url.path().trim_end_matches('/').to_owned(),
+        Err(_) => "".to_owned(),
+    }
 }
 
 
-impl From<ResponseWrapper> for BatchResponseWrapper {
-    fn from(batch_wrapper: ResponseWrapper) -> Self {
+/// Read a mandatory field of a batch subresponse.
+fn subresponse_field<'a>(resp: &'a Value, field: &str) -> Result<&'a Value, KintoError> {
+    match resp.get(field) {
+        Some(value) => Ok(value),
+        None => {
+            let message = format!("Missing {} in batch subresponse", field);
+            Err(KintoError::InvalidResponseError(message))
+        }
+    }
+}
+
+
+impl BatchResponseWrapper {
+    /// Parse the subresponses of a `/batch` response and match them with the
+    /// sent requests, in order.
+    ///
+    /// A partial response (e.g. of a `PartialBatchError`) matches the first
+    /// requests, but there can not be more subresponses than requests.
+    pub fn from_response(batch_wrapper: ResponseWrapper,
+                         requests: &[RequestPreparer])
+                         -> Result<BatchResponseWrapper, KintoError> {
         let mut responses = vec![];
+        let prefix = server_path_prefix(&batch_wrapper.config.server_url);
 
-        for resp in batch_wrapper
-                .body
-                .get("responses")
-                .unwrap()
-                .as_array()
-                .unwrap() {
-            let wrapper = ResponseWrapper {
-                config: batch_wrapper.config.clone(),
-                // XXX: Unwrap headers
-                headers: Headers::new(),
-                body: resp.get("body").unwrap().clone(),
-                // XXX: Avoid version hardcodes
-                path: resp.get("path")
-                    .unwrap()
-                    .as_str()
-                    .unwrap()
-                    .replace("/v1/", "/")
-                    .to_owned(),
-                status: StatusCode::Unregistered(resp.get("status")
-                                                     .unwrap()
-                                                     .as_u64()
-                                                     .unwrap() as
-                                                 u16),
+        let subresponses = match batch_wrapper.body["responses"].as_array() {
+            Some(subresponses) => subresponses.clone(),
+            None => {
+                let message = "Missing batch responses".to_owned();
+                return Err(KintoError::InvalidResponseError(message));
+            }
+        };
+        if subresponses.len() > requests.len() {
+            let message = format!("Got {} batch responses for {} requests",
+                                  subresponses.len(),
+                                  requests.len());
+            return Err(KintoError::InvalidResponseError(message));
+        }
+
+        for resp in subresponses {
+            let status = match try!(subresponse_field(&resp, "status")).as_u64() {
+                Some(status) => StatusCode::from_u16(status as u16),
+                None => {
+                    let message = "Invalid subresponse status".to_owned();
+                    return Err(KintoError::InvalidResponseError(message));
+                }
             };
-            responses.push(wrapper);
+
+            let path = try!(subresponse_field(&resp, "path"))
+                .as_str()
+                .unwrap_or("")
+                .to_owned();
+            let path = if !prefix.is_empty() && path.starts_with(&prefix) {
+                path[prefix.len()..].to_owned()
+            } else {
+                path
+            };
+
+            let mut headers = Headers::new();
+            if let Some(map) = resp["headers"].as_object() {
+                for (name, value) in map {
+                    let value = value.as_str().unwrap_or("").as_bytes().to_vec();
+                    headers.set_raw(name.to_owned(), vec![value]);
+                }
+            }
+
+            responses.push(ResponseWrapper {
+                               config: batch_wrapper.config.clone(),
+                               headers,
+                               body: resp["body"].clone(),
+                               path,
+                               status,
+                           });
         }
 
-        BatchResponseWrapper {
-            config: batch_wrapper.config,
-            status: batch_wrapper.status,
-            headers: batch_wrapper.headers,
-            responses: responses,
+        Ok(BatchResponseWrapper {
+               config: batch_wrapper.config,
+               status: batch_wrapper.status,
+               headers: batch_wrapper.headers,
+               requests: requests[..responses.len()].to_vec(),
+               responses,
+           })
+    }
+
+    /// Get the result of each subrequest, with the server error on failures.
+    pub fn results(&self) -> Vec<Result<ResponseWrapper, KintoError>> {
+        self.responses
+            .iter()
+            .map(|resp| {
+                     ResponseWrapper::from_parts(resp.config.clone(),
+                                                 resp.path.clone(),
+                                                 resp.status,
+                                                 resp.headers.clone(),
+                                                 &resp.body.to_string())
+                 })
+            .collect()
+    }
+
+    /// Get the requests which failed, to send them again.
+    pub fn failed_requests(&self) -> Vec<RequestPreparer> {
+        self.requests
+            .iter()
+            .zip(self.responses.iter())
+            .filter(|&(_, resp)| !resp.status.is_success())
+            .map(|(req, _)| req.clone())
+            .collect()
+    }
+}
+
+
+/// Record operations collected by `Collection::batch`.
+#[derive(Debug)]
+pub struct RecordBatch {
+    pub request: BatchRequest,
+    collection: Collection,
+    records: Vec<Record>,
+    error: Option<KintoError>,
+}
+
+
+impl RecordBatch {
+    pub fn new(collection: Collection) -> RecordBatch {
+        RecordBatch {
+            request: BatchRequest::new(collection.get_config()),
+            collection,
+            records: vec![],
+            error: None,
         }
     }
+
+    /// Queue a request, keeping the first preparation error.
+    fn push<T>(&mut self, request: Result<T, KintoError>, record: Record) -> &mut Self
+        where T: KintoRequest
+    {
+        match request {
+            Ok(request) => {
+                self.request.add_request(request);
+                self.records.push(record);
+            }
+            Err(err) => {
+                if self.error.is_none() {
+                    self.error = Some(err);
+                }
+            }
+        }
+        self
+    }
+
+    /// Create the record if it does not exist.
+    pub fn create(&mut self, record: Record) -> &mut Self {
+        let request = record
+            .create_request()
+            .map(|mut request| {
+                     request
+                         .body(record.get_body().into())
+                         .if_none_match(IfNoneMatch::Any);
+                     request
+                 });
+        self.push(request, record)
+    }
+
+    /// Create or replace the record.
+    pub fn set(&mut self, record: Record) -> &mut Self {
+        if record.get_id().is_none() {
+            return self.create(record);
+        }
+        let request = record
+            .update_request()
+            .map(|mut request| {
+                     request.body(record.get_body().into());
+                     request
+                 });
+        self.push(request, record)
+    }
+
+    /// Update the existing record.
+    pub fn update(&mut self, record: Record) -> &mut Self {
+        let if_match = match record.get_timestamp() {
+            Some(stamp) => IfMatch::Items(timestamp_to_etag(stamp)),
+            None => IfMatch::Any,
+        };
+        let request = record
+            .update_request()
+            .map(|mut request| {
+                     request
+                         .body(record.get_body().into())
+                         .if_match(if_match);
+                     request
+                 });
+        self.push(request, record)
+    }
+
+    /// Replace the record if it was not modified since the given timestamp.
+    pub fn update_if_unchanged(&mut self, record: Record, timestamp: u64) -> &mut Self {
+        let request = record
+            .update_request()
+            .map(|mut request| {
+                     request
+                         .body(record.get_body().into())
+                         .if_match(IfMatch::Items(timestamp_to_etag(timestamp)));
+                     request
+                 });
+        self.push(request, record)
+    }
+
+    /// Delete the record with the given id.
+    pub fn delete(&mut self, id: &str) -> &mut Self {
+        let record = self.collection.record(id);
+        let request = record.delete_request();
+        self.push(request, record)
+    }
+
+    /// Delete the record if it was not modified since the given timestamp.
+    pub fn delete_if_unchanged(&mut self, id: &str, timestamp: u64) -> &mut Self {
+        let record = self.collection.record(id);
+        let request = record
+            .delete_request()
+            .map(|mut request| {
+                     request.if_match(IfMatch::Items(timestamp_to_etag(timestamp)));
+                     request
+                 });
+        self.push(request, record)
+    }
+
+    /// Send the operations and apply each response to its record.
+    ///
+    /// On `KintoError::PartialBatchError`, the operations of the committed
+    /// chunks are the first ones, matching the subresponses of the error.
+    pub fn send(mut self) -> Result<Vec<Result<Record, KintoError>>, KintoError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.records.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let response = try!(self.request.send_batch());
+        let results = response
+            .results()
+            .into_iter()
+            .zip(self.records)
+            .map(|(result, mut record)| {
+                     let wrapper = try!(result);
+                     record.unwrap_response(wrapper);
+                     Ok(record)
+                 })
+            .collect();
+        Ok(results)
+    }
 }
 
 
@@ -123,10 +509,16 @@ mod test_record {
 
     use hyper::status::StatusCode;
     use hyper::method::Method;
+    use hyper::header::{Headers, ETag, IfNoneMatch};
 
-    use batch::{BatchRequest, BatchResponseWrapper};
+    use batch::{BatchRequest, BatchResponseWrapper, DEFAULT_BATCH_MAX_REQUESTS};
+    use error::KintoError;
+    use server_info::ServerInfo;
     use request::KintoRequest;
     use resource::Resource;
+    use response::ResponseWrapper;
+    use request::PayloadedEndpoint;
+    use bucket::Bucket;
     use utils::tests::{setup_config, setup_bucket};
 
     #[test]
@@ -135,7 +527,7 @@ mod test_record {
         let bucket = setup_bucket();
         let mut batch = BatchRequest::new(config);
         batch.add_request(bucket.update_request().unwrap());
-        let result: BatchResponseWrapper = batch.send().unwrap().into();
+        let result = batch.send_batch().unwrap();
         assert_eq!(result.responses.len(), 1);
         assert_eq!(result.responses[0].status, StatusCode::Created);
         assert_eq!(result.responses[0].path, "/buckets/food");
@@ -149,10 +541,239 @@ mod test_record {
         let mut batch = BatchRequest::new(config);
         batch.add_request(bucket.update_request().unwrap());
         batch.add_request(bucket.delete_request().unwrap());
-        let result: BatchResponseWrapper = batch.send().unwrap().into();
-        assert_eq!(batch.requests[0].method, Method::Put);
-        assert_eq!(batch.requests[1].method, Method::Delete);
+        let result = batch.send_batch().unwrap();
+        assert_eq!(result.requests[0].method, Method::Put);
+        assert_eq!(result.requests[1].method, Method::Delete);
         assert_eq!(result.responses[0].status, StatusCode::Created);
         assert_eq!(result.responses[1].status, StatusCode::Ok);
     }
+
+    #[test]
+    fn test_batch_is_split_in_chunks() {
+        let config = setup_config();
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+        let collection = bucket.collection("meat");
+
+        let mut batch = BatchRequest::new(config);
+        batch.add_request(collection.update_request().unwrap());
+        for _ in 0..60 {
+            let record = collection.new_record();
+            batch.add_request(record.create_request().unwrap());
+        }
+        let result = batch.send_batch().unwrap();
+        assert_eq!(result.responses.len(), 61);
+        assert_eq!(result.responses[0].path, "/buckets/food/collections/meat");
+        assert_eq!(result.requests.len(), 61);
+    }
+
+    #[test]
+    fn test_batch_results() {
+        let config = setup_config();
+        let bucket = setup_bucket();
+        let mut batch = BatchRequest::new(config);
+        batch.add_request(bucket.update_request().unwrap());
+        let mut create = bucket.create_request().unwrap();
+        create.if_none_match(IfNoneMatch::Any);
+        batch.add_request(create);
+        batch.add_request(bucket.collection("meat").load_request().unwrap());
+        let result = batch.send_batch().unwrap();
+
+        assert!(result.responses[0].headers.get::<ETag>().is_some());
+
+        let results = result.results();
+        assert!(results[0].is_ok());
+        assert_eq!(results[2].as_ref().unwrap_err().status(),
+                   Some(StatusCode::NotFound));
+
+        let failed = result.failed_requests();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].path, "/buckets/food/collections/meat");
+    }
+
+    #[test]
+    fn test_malformed_batch_response() {
+        let response = ResponseWrapper {
+            config: setup_config(),
+            path: "/batch".to_owned(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: json!({"responses": [{"path": "/v1/buckets/food", "body": {}}]}),
+        };
+        let bucket = Bucket::new_by_id(setup_config(), "food");
+        let requests = [bucket.load_request().unwrap().preparer().clone()];
+        BatchResponseWrapper::from_response(response, &requests).unwrap_err();
+    }
+
+    #[test]
+    fn test_batch_response_without_requests() {
+        let response = ResponseWrapper {
+            config: setup_config(),
+            path: "/batch".to_owned(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: json!({"responses": [{"status": 200, "path": "/v1/buckets/food"}]}),
+        };
+        BatchResponseWrapper::from_response(response, &[]).unwrap_err();
+    }
+
+    #[test]
+    fn test_batch_chunk_failure() {
+        let bucket = setup_bucket();
+        let mut batch = BatchRequest::new(setup_config());
+        batch.max_requests = 1;
+        batch.add_request(bucket.update_request().unwrap());
+        let mut invalid = bucket.load_request().unwrap();
+        invalid.preparer().method = Method::Extension("INVALID".to_owned());
+        batch.add_request(invalid);
+
+        match batch.send().unwrap_err() {
+            KintoError::PartialBatchError { response, error } => {
+                let result = BatchResponseWrapper::from_response(*response,
+                                                                 &batch.requests)
+                        .unwrap();
+                assert_eq!(result.responses.len(), 1);
+                assert_eq!(result.requests[0].method, Method::Put);
+                assert_eq!(result.responses[0].status, StatusCode::Created);
+                assert_eq!(error.status(), Some(StatusCode::BadRequest));
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_parse_batch_response() {
+        let response = ResponseWrapper {
+            config: setup_config(),
+            path: "/batch".to_owned(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: json!({"responses": [{
+                "status": 201,
+                "path": "/v1/buckets/food",
+                "body": {"data": {"id": "food"}},
+                "headers": {"ETag": "\"42\""},
+            }]}),
+        };
+        let bucket = Bucket::new_by_id(setup_config(), "food");
+        let requests = [bucket.update_request().unwrap().preparer().clone()];
+        let result = BatchResponseWrapper::from_response(response, &requests).unwrap();
+        assert_eq!(result.responses[0].status, StatusCode::Created);
+        assert_eq!(result.requests[0].method, Method::Put);
+        assert_eq!(result.responses[0].path, "/buckets/food");
+        assert_eq!(result.responses[0].etag_timestamp(), Some(42));
+    }
+
+    #[test]
+    fn test_batch_body_defaults() {
+        let bucket = Bucket::new_by_id(setup_config(), "food");
+        let mut batch = BatchRequest::new(setup_config());
+        batch
+            .default_method(Method::Put)
+            .default_path("/buckets/food")
+            .default_header("Content-Type", "application/json");
+        batch.add_request(bucket.load_request().unwrap());
+        let mut update = bucket.update_request().unwrap();
+        update.body(json!({}).into());
+        batch.add_request(update);
+        batch.add_request(bucket.collection("meat").update_request().unwrap());
+
+        let body = batch.preparer().body.clone().unwrap();
+        assert_eq!(body["defaults"]["method"], "PUT");
+        assert_eq!(body["defaults"]["path"], "/buckets/food");
+        assert_eq!(body["defaults"]["headers"]["Content-Type"], "application/json");
+
+        let requests = body["requests"].as_array().unwrap();
+        assert_eq!(requests[0]["method"], "GET");
+        assert!(requests[0].get("path").is_none());
+        assert!(requests[0].get("body").is_none());
+        assert!(requests[1].get("method").is_none());
+        assert!(requests[1].get("path").is_none());
+        assert_eq!(requests[1]["body"], json!({}));
+        assert!(requests[2].get("method").is_none());
+        assert_eq!(requests[2]["path"], "/buckets/food/collections/meat");
+    }
+
+    #[test]
+    fn test_batch_body_without_defaults() {
+        let mut batch = BatchRequest::new(setup_config());
+        let body = batch.preparer().body.clone().unwrap();
+        assert!(body.get("defaults").is_none());
+    }
+
+    #[test]
+    fn test_record_batch() {
+        let mut collection = setup_bucket().collection("meat");
+        collection.bucket.set().unwrap();
+        collection.create().unwrap();
+        let mut existing = collection.record("existing");
+        existing.set().unwrap();
+        let mut removed = collection.record("removed");
+        removed.set().unwrap();
+
+        let mut created = collection.new_record();
+        created.data = json!({"good": true}).into();
+        existing.data = json!({"updated": true}).into();
+
+        let results = collection
+            .batch(|b| {
+                       b.create(created);
+                       b.update(existing);
+                       b.delete("removed");
+                       b.delete("unknown");
+                   })
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        let created = results[0].as_ref().unwrap();
+        assert!(created.get_id().is_some());
+        assert_eq!(created.data.as_ref().unwrap()["good"], true);
+        let updated = results[1].as_ref().unwrap();
+        assert_eq!(updated.data.as_ref().unwrap()["updated"], true);
+        let deleted = results[2].as_ref().unwrap();
+        assert_eq!(deleted.data.as_ref().unwrap()["deleted"], true);
+        assert_eq!(results[3].as_ref().unwrap_err().status(),
+                   Some(StatusCode::NotFound));
+    }
+
+    #[test]
+    fn test_record_batch_if_unchanged() {
+        let mut collection = setup_bucket().collection("meat");
+        collection.bucket.set().unwrap();
+        collection.create().unwrap();
+        let mut first = collection.record("first");
+        first.set().unwrap();
+        let mut second = collection.record("second");
+        second.set().unwrap();
+        let timestamp = second.get_timestamp().unwrap();
+
+        let results = collection
+            .batch(|b| {
+                       b.update_if_unchanged(collection.record("first"), timestamp);
+                       b.delete_if_unchanged("first", timestamp);
+                       b.delete_if_unchanged("second", timestamp);
+                   })
+            .unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap_err().status(),
+                   Some(StatusCode::PreconditionFailed));
+        assert_eq!(results[1].as_ref().unwrap_err().status(),
+                   Some(StatusCode::PreconditionFailed));
+        assert!(results[2].is_ok());
+        first.load().unwrap();
+    }
+
+    #[test]
+    fn test_limit_from_server_info() {
+        let mut batch = BatchRequest::new(setup_config());
+        assert_eq!(batch.max_requests, DEFAULT_BATCH_MAX_REQUESTS);
+
+        let mut info = ServerInfo::default();
+        batch.limit_from(&info);
+        assert_eq!(batch.max_requests, DEFAULT_BATCH_MAX_REQUESTS);
+
+        info.settings.batch_max_requests = Some(10);
+        batch.limit_from(&info);
+        assert_eq!(batch.max_requests, 10);
+    }
 }
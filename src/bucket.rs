@@ -3,12 +3,15 @@ use serde_json::Value;
 
 use KintoConfig;
 use error::KintoError;
-use request::KintoRequest;
+use request::{KintoRequest, GetCollection, HeadCollection};
 use response::ResponseWrapper;
 use resource::Resource;
 use collection::Collection;
+use group::Group;
+use history::HistoryEntry;
+use paginator::Paginator;
 
-use utils::unwrap_collection_records;
+use utils::{unwrap_collection_records, unwrap_permissions};
 
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -70,11 +73,58 @@ impl Bucket {
         Ok(unwrap_collection_records(&response, &self.new_collection()))
     }
 
+    /// Iterate over the changes of the bucket and its objects, most recent
+    /// first (history plugin).
+    ///
+    /// Filters (e.g. `resource_name`, `collection_id`, `user_id` or `since`)
+    /// can be set on the paginator `request`.
+    pub fn history(&self) -> Result<Paginator<HistoryEntry, GetCollection>, KintoError> {
+        let path = format!("{}/history", try!(self.record_path()));
+        let request = GetCollection::new(self.get_config(), path);
+        Ok(Paginator::new(request, HistoryEntry::default()))
+    }
+
+    /// Count the collections without downloading them.
+    pub fn count_collections(&self) -> Result<u64, KintoError> {
+        try!(self.new_collection().count_request()).count()
+    }
+
+    /// Count the collections matching the filters set on the request.
+    pub fn count_collections_with<F>(&self, filter: F) -> Result<u64, KintoError>
+        where F: FnOnce(&mut HeadCollection)
+    {
+        let mut request = try!(self.new_collection().count_request());
+        filter(&mut request);
+        request.count()
+    }
+
     /// Delete all available collections.
     pub fn delete_collections(&self) -> Result<(), KintoError> {
         try!(try!(self.new_collection().delete_all_request()).follow_subrequests());
         Ok(())
     }
+
+    /// Get a group by id.
+    pub fn group(&self, id: &str) -> Group {
+        Group::new_by_id(self.clone(), id)
+    }
+
+    /// Get an empty group.
+    pub fn new_group(&self) -> Group {
+        Group::new(self.clone())
+    }
+
+    /// List all available groups.
+    pub fn list_groups(&self) -> Result<Vec<Group>, KintoError> {
+        let response = try!(try!(self.new_group().list_request()).follow_subrequests());
+        Ok(unwrap_collection_records(&response, &self.new_group()))
+    }
+
+    /// Delete all available groups.
+    pub fn delete_groups(&self) -> Result<(), KintoError> {
+        try!(try!(self.new_group().delete_all_request()).follow_subrequests());
+        Ok(())
+    }
 }
 
 
@@ -84,9 +134,8 @@ impl Resource for Bucket {
     }
 
     fn unwrap_response(&mut self, wrapper: ResponseWrapper) {
-        self.data = Some(wrapper.body["data"].to_owned());
-        self.permissions = serde_json::from_value(wrapper.body["permissions"].to_owned())
-            .unwrap();
+        self.data = Some(wrapper.data());
+        self.permissions = unwrap_permissions(&wrapper);
         self.id = Some(wrapper.body["data"]["id"].as_str().unwrap().to_owned());
     }
 
@@ -108,7 +157,7 @@ impl Resource for Bucket {
     fn get_timestamp(&self) -> Option<u64> {
         match self.get_data() {
             Some(data) => {
-                match data["lat_modified"].as_u64() {
+                match data["last_modified"].as_u64() {
                     Some(ts) => ts.into(),
                     None => None,
                 }
@@ -142,7 +191,7 @@ impl Resource for Bucket {
 mod test_bucket_resource {
     use utils::tests::{setup_client, setup_bucket};
     use resource::Resource;
-    use bucket::BucketPermissions;
+    use bucket::{Bucket, BucketPermissions};
 
     #[test]
     fn test_set_bucket() {
@@ -224,6 +273,27 @@ mod test_bucket_resource {
         assert_eq!(permissions.write.unwrap().len(), 2);
     }
 
+    #[test]
+    fn test_grant_and_revoke_bucket_permissions() {
+        let mut bucket = setup_bucket();
+        bucket.data = json!({"good": true}).into();
+        bucket.permissions.read = Some(vec!["alice".to_owned()]);
+        bucket.create().unwrap();
+
+        bucket.grant("read", "bob").unwrap();
+        bucket.grant("collection:create", "bob").unwrap();
+        {
+            let read = bucket.permissions.read.as_ref().unwrap();
+            assert!(read.contains(&"alice".to_owned()));
+            assert!(read.contains(&"bob".to_owned()));
+        }
+        assert_eq!(bucket.permissions.create_collection.as_ref().unwrap()[0], "bob");
+
+        bucket.revoke("read", "alice").unwrap();
+        assert_eq!(bucket.permissions.read.clone().unwrap(), vec!["bob"]);
+        assert_eq!(bucket.data.unwrap()["good"], true);
+    }
+
     #[test]
     fn test_load_bucket_fails_without_id() {
         let mut bucket = setup_bucket();
@@ -262,6 +332,20 @@ mod test_bucket_resource {
         assert!(create_data["last_modified"] != update_data["last_modified"]);
     }
 
+    #[test]
+    fn test_update_bucket_fails_on_conflict() {
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+
+        let mut other = Bucket::new_by_id(bucket.config.clone(), "food");
+        other.load().unwrap();
+        other.update().unwrap();
+
+        let err = bucket.update().unwrap_err();
+        assert_eq!(err.existing().unwrap()["last_modified"],
+                   other.data.unwrap()["last_modified"]);
+    }
+
     #[test]
     fn test_update_bucket_fails_on_not_existing() {
         let client = setup_client();
@@ -274,6 +358,7 @@ mod test_bucket_resource {
 #[cfg(test)]
 mod test_bucket_class {
     use utils::tests::setup_bucket;
+    use request::PluralEndpoint;
     use resource::Resource;
 
     #[test]
@@ -291,4 +376,78 @@ mod test_bucket_class {
         assert_eq!(collection.data, None);
         assert_eq!(collection.get_id(), None);
     }
+
+    #[test]
+    fn test_get_group() {
+        let bucket = setup_bucket();
+        let group = bucket.group("editors");
+        assert_eq!(group.get_id().unwrap(), "editors");
+        assert_eq!(group.data, None);
+    }
+
+    #[test]
+    fn test_new_group() {
+        let bucket = setup_bucket();
+        let group = bucket.new_group();
+        assert_eq!(group.data, None);
+        assert_eq!(group.get_id(), None);
+    }
+
+    #[test]
+    fn test_list_groups() {
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+        for _ in 0..3 {
+            bucket.new_group().create().unwrap();
+        }
+        assert_eq!(bucket.list_groups().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_bucket_history() {
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+        bucket.clone().collection("meat").create().unwrap();
+        bucket.new_group().create().unwrap();
+
+        let entries: Vec<_> = bucket.history().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].resource_name, "group");
+        assert_eq!(entries[2].action, "create");
+
+        let mut history = bucket.history().unwrap();
+        history.request.eq("resource_name", "collection");
+        let entries: Vec<_> = history.map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].collection_id.clone().unwrap(), "meat");
+    }
+
+    #[test]
+    fn test_count_collections() {
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+        assert_eq!(bucket.count_collections().unwrap(), 0);
+
+        for _ in 0..3 {
+            bucket.new_collection().create().unwrap();
+        }
+        bucket.clone().collection("meat").create().unwrap();
+
+        assert_eq!(bucket.count_collections().unwrap(), 4);
+        let meat = bucket.count_collections_with(|request| {
+                                                     request.eq("id", "meat");
+                                                 });
+        assert_eq!(meat.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_delete_groups() {
+        let mut bucket = setup_bucket();
+        bucket.create().unwrap();
+        for _ in 0..3 {
+            bucket.new_group().create().unwrap();
+        }
+        bucket.delete_groups().unwrap();
+        assert_eq!(bucket.list_groups().unwrap().len(), 0);
+    }
 }
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[cfg(feature = "async")]
+use futures::future::FutureExt;
+use serde_json;
+use serde_json::Value;
+use hyper::header::Headers;
+use hyper::status::StatusCode;
+
+use KintoConfig;
+use error::KintoError;
+use request::{RequestPreparer, send_prepared};
+use response::ResponseWrapper;
+#[cfg(feature = "async")]
+use async_client::{KintoFuture, send_prepared_async};
+
+
+/// Default maximum number of responses kept by a cache.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1000;
+
+
+/// Successful response stored by a `ResponseCache`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// Raw `ETag` header, sent back as `If-None-Match`.
+    pub etag: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+}
+
+
+impl CachedResponse {
+    /// Store a response, if it has an `ETag` header.
+    pub fn from_response(wrapper: &ResponseWrapper) -> Option<CachedResponse> {
+        let etag = match wrapper.headers.get_raw("ETag") {
+            Some(values) => String::from_utf8_lossy(&values[0]).into_owned(),
+            None => return None,
+        };
+        let headers = wrapper
+            .headers
+            .iter()
+            .map(|header| (header.name().to_owned(), header.value_string()))
+            .collect();
+        Some(CachedResponse {
+                 etag,
+                 headers,
+                 body: wrapper.body.to_owned(),
+             })
+    }
+
+    /// Build back the response.
+    pub fn to_response(&self, config: KintoConfig, path: String) -> ResponseWrapper {
+        let mut headers = Headers::new();
+        for (name, value) in self.headers.iter() {
+            headers.append_raw(name.to_owned(), value.as_bytes().to_vec());
+        }
+        ResponseWrapper {
+            config,
+            path,
+            status: StatusCode::Ok,
+            headers,
+            body: self.body.to_owned(),
+        }
+    }
+}
+
+
+/// Storage of responses revalidated with `If-None-Match`.
+///
+/// Entries are keyed by request url. As they are not keyed by user, a cache
+/// should not be shared between configs with different credentials.
+pub trait ResponseCache: fmt::Debug + Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    fn put(&self, key: &str, response: CachedResponse);
+
+    fn remove(&self, key: &str);
+}
+
+
+/// Entries of a `MemoryCache`, with the tick of their last use.
+#[derive(Debug, Default)]
+struct MemoryCacheEntries {
+    entries: HashMap<String, (u64, CachedResponse)>,
+    clock: u64,
+}
+
+
+impl MemoryCacheEntries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+
+/// Cache keeping responses in memory.
+///
+/// The least recently used responses are evicted beyond its capacity.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<MemoryCacheEntries>,
+}
+
+
+impl MemoryCache {
+    pub fn new() -> MemoryCache {
+        MemoryCache::with_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Create a cache keeping at most `capacity` responses.
+    pub fn with_capacity(capacity: usize) -> MemoryCache {
+        MemoryCache {
+            capacity,
+            entries: Mutex::new(MemoryCacheEntries::default()),
+        }
+    }
+}
+
+
+impl Default for MemoryCache {
+    fn default() -> MemoryCache {
+        MemoryCache::new()
+    }
+}
+
+
+impl ResponseCache for MemoryCache {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let tick = entries.tick();
+        match entries.entries.get_mut(key) {
+            Some(entry) => {
+                entry.0 = tick;
+                Some(entry.1.clone())
+            }
+            None => None,
+        }
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        let tick = entries.tick();
+        entries.entries.insert(key.to_owned(), (tick, response));
+
+        while entries.entries.len() > self.capacity {
+            let oldest = entries
+                .entries
+                .iter()
+                .min_by_key(|&(_, entry)| entry.0)
+                .map(|(key, _)| key.to_owned())
+                .unwrap();
+            entries.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().entries.remove(key);
+    }
+}
+
+
+/// Entry written on disk by a `FileCache`.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileCacheEntry {
+    key: String,
+    response: CachedResponse,
+}
+
+
+/// Cache keeping responses as JSON files in a directory.
+///
+/// The least recently used files are evicted beyond its capacity, using
+/// their modification time. Disk errors are ignored: the request is then
+/// simply sent again.
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    directory: PathBuf,
+    capacity: usize,
+}
+
+
+impl FileCache {
+    /// Create a cache in the given directory, created if needed.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Result<FileCache, KintoError> {
+        FileCache::with_capacity(directory, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Create a cache keeping at most `capacity` responses in the given
+    /// directory, created if needed.
+    pub fn with_capacity<P: Into<PathBuf>>(directory: P,
+                                           capacity: usize)
+                                           -> Result<FileCache, KintoError> {
+        let directory = directory.into();
+        try!(fs::create_dir_all(&directory));
+        Ok(FileCache {
+               directory,
+               capacity,
+           })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.directory.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    /// Remove the least recently used entries beyond the capacity.
+    fn evict(&self) {
+        let files = match fs::read_dir(&self.directory) {
+            Ok(files) => files,
+            Err(_) => return,
+        };
+        let mut entries: Vec<(SystemTime, PathBuf)> = files
+            .filter_map(|file| file.ok())
+            .map(|file| file.path())
+            .filter(|path| path.extension() == Some("json".as_ref()))
+            .filter_map(|path| {
+                            let modified = fs::metadata(&path)
+                                .and_then(|metadata| metadata.modified());
+                            modified.ok().map(|modified| (modified, path))
+                        })
+            .collect();
+        if entries.len() <= self.capacity {
+            return;
+        }
+
+        entries.sort();
+        let evicted = entries.len() - self.capacity;
+        for (_, path) in entries.iter().take(evicted) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+
+impl ResponseCache for FileCache {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let file = match fs::File::open(self.entry_path(key)) {
+            Ok(file) => file,
+            Err(_) => return None,
+        };
+        match serde_json::from_reader::<_, FileCacheEntry>(&file) {
+            // Make sure the entry is not a hash collision
+            Ok(entry) if entry.key == key => {
+                // Mark the entry as recently used
+                let _ = file.set_modified(SystemTime::now());
+                Some(entry.response)
+            }
+            _ => None,
+        }
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let entry = FileCacheEntry {
+            key: key.to_owned(),
+            response,
+        };
+        if let Ok(file) = fs::File::create(self.entry_path(key)) {
+            if serde_json::to_writer(&file, &entry).is_ok() {
+                let _ = file.set_modified(SystemTime::now());
+            }
+        }
+        self.evict();
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.entry_path(key));
+    }
+}
+
+
+/// Read request revalidating a cached response.
+struct CachedRead {
+    cache: Arc<dyn ResponseCache>,
+    key: String,
+    cached: Option<CachedResponse>,
+    /// Request sent with the `If-None-Match` of the cached response.
+    conditional: RequestPreparer,
+}
+
+
+impl CachedRead {
+    /// Prepare the conditional request, if the config has a cache.
+    ///
+    /// Requests with explicit conditional headers bypass the cache.
+    fn new(preparer: &RequestPreparer) -> Option<CachedRead> {
+        let cache = match preparer.config.cache {
+            Some(ref cache) => cache.clone(),
+            None => return None,
+        };
+        if preparer.headers.get_raw("If-None-Match").is_some() ||
+           preparer.headers.get_raw("If-Match").is_some() {
+            return None;
+        }
+
+        let key = preparer.full_url();
+        let cached = cache.get(&key);
+
+        let mut conditional = preparer.clone();
+        if let Some(ref entry) = cached {
+            conditional
+                .headers
+                .set_raw("If-None-Match", vec![entry.etag.as_bytes().to_vec()]);
+        }
+
+        Some(CachedRead {
+                 cache,
+                 key,
+                 cached,
+                 conditional,
+             })
+    }
+
+    /// Update the cache with the result of the conditional request.
+    fn finish(self,
+              result: Result<ResponseWrapper, KintoError>)
+              -> Result<ResponseWrapper, KintoError> {
+        match result {
+            Ok(wrapper) => {
+                match CachedResponse::from_response(&wrapper) {
+                    Some(entry) => self.cache.put(&self.key, entry),
+                    None => self.cache.remove(&self.key),
+                }
+                Ok(wrapper)
+            }
+            Err(KintoError::NotModified) => {
+                match self.cached {
+                    Some(entry) => {
+                        let path = self.conditional.path.to_owned();
+                        Ok(entry.to_response(self.conditional.config, path))
+                    }
+                    None => Err(KintoError::NotModified),
+                }
+            }
+            Err(err) => {
+                // Do not keep responses of deleted objects
+                if err.status() == Some(StatusCode::NotFound) {
+                    self.cache.remove(&self.key);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+
+/// Send a read request, revalidating the cached response if any.
+///
+/// Requests with explicit conditional headers bypass the cache.
+pub fn send_cached(preparer: &RequestPreparer) -> Result<ResponseWrapper, KintoError> {
+    match CachedRead::new(preparer) {
+        Some(read) => {
+            let result = send_prepared(&read.conditional);
+            read.finish(result)
+        }
+        None => send_prepared(preparer),
+    }
+}
+
+
+/// Send a read request with the asynchronous client, revalidating the
+/// cached response like `send_cached`.
+#[cfg(feature = "async")]
+pub fn send_cached_async(preparer: RequestPreparer) -> KintoFuture<ResponseWrapper> {
+    match CachedRead::new(&preparer) {
+        Some(read) => {
+            let response = send_prepared_async(read.conditional.clone());
+            Box::pin(response.map(move |result| read.finish(result)))
+        }
+        None => send_prepared_async(preparer),
+    }
+}
+
+
+#[cfg(test)]
+mod test_cache {
+    use std::env;
+    use std::fs;
+
+    use hyper::header::Headers;
+    use hyper::status::StatusCode;
+
+    use cache::{CachedResponse, ResponseCache, MemoryCache, FileCache};
+    use request::{KintoRequest, GetCollection};
+    use resource::Resource;
+    use response::ResponseWrapper;
+    use utils::tests::{setup_config, setup_collection};
+
+    fn cached_response() -> CachedResponse {
+        CachedResponse {
+            etag: "\"42\"".to_owned(),
+            headers: vec![("ETag".to_owned(), "\"42\"".to_owned())],
+            body: json!({"data": [{"id": "a", "last_modified": 42}]}),
+        }
+    }
+
+    #[test]
+    fn test_cached_response_round_trip() {
+        let mut headers = Headers::new();
+        headers.set_raw("ETag", vec![b"\"42\"".to_vec()]);
+        let wrapper = ResponseWrapper {
+            config: setup_config(),
+            path: "/buckets".to_owned(),
+            status: StatusCode::Ok,
+            headers,
+            body: json!({"data": [{"id": "a", "last_modified": 42}]}),
+        };
+
+        let entry = CachedResponse::from_response(&wrapper).unwrap();
+        assert_eq!(entry, cached_response());
+
+        let response = entry.to_response(setup_config(), "/buckets".to_owned());
+        assert_eq!(response.etag_timestamp(), Some(42));
+        assert_eq!(response.body, wrapper.body);
+    }
+
+    #[test]
+    fn test_response_without_etag_is_not_cached() {
+        let wrapper = ResponseWrapper {
+            config: setup_config(),
+            path: "/".to_owned(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: json!({}),
+        };
+        assert_eq!(CachedResponse::from_response(&wrapper), None);
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        let cache = MemoryCache::new();
+        assert_eq!(cache.get("/buckets"), None);
+
+        cache.put("/buckets", cached_response());
+        assert_eq!(cache.get("/buckets"), Some(cached_response()));
+
+        cache.remove("/buckets");
+        assert_eq!(cache.get("/buckets"), None);
+    }
+
+    #[test]
+    fn test_memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::with_capacity(2);
+        cache.put("/buckets/a", cached_response());
+        cache.put("/buckets/b", cached_response());
+        cache.get("/buckets/a").unwrap();
+
+        cache.put("/buckets/c", cached_response());
+        assert!(cache.get("/buckets/a").is_some());
+        assert_eq!(cache.get("/buckets/b"), None);
+        assert!(cache.get("/buckets/c").is_some());
+    }
+
+    #[test]
+    fn test_file_cache_evicts_least_recently_used() {
+        let directory = env::temp_dir().join("kinto-http-test-file-cache-eviction");
+        let _ = fs::remove_dir_all(&directory);
+        let cache = FileCache::with_capacity(directory, 2).unwrap();
+        cache.put("/buckets/a", cached_response());
+        cache.put("/buckets/b", cached_response());
+        cache.get("/buckets/a").unwrap();
+
+        cache.put("/buckets/c", cached_response());
+        assert!(cache.get("/buckets/a").is_some());
+        assert_eq!(cache.get("/buckets/b"), None);
+        assert!(cache.get("/buckets/c").is_some());
+    }
+
+    #[test]
+    fn test_file_cache() {
+        let directory = env::temp_dir().join("kinto-http-test-file-cache");
+        let cache = FileCache::new(directory).unwrap();
+        cache.remove("/buckets?_limit=2");
+        assert_eq!(cache.get("/buckets?_limit=2"), None);
+
+        cache.put("/buckets?_limit=2", cached_response());
+        assert_eq!(cache.get("/buckets?_limit=2"), Some(cached_response()));
+        assert_eq!(cache.get("/buckets"), None);
+
+        cache.remove("/buckets?_limit=2");
+        assert_eq!(cache.get("/buckets?_limit=2"), None);
+    }
+
+    #[test]
+    fn test_collection_list_is_revalidated() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        collection.new_record().create().unwrap();
+
+        let config = setup_config().with_cache(MemoryCache::new());
+        let path = collection.record_path().unwrap() + "/records";
+        let mut request = GetCollection::new(config.clone(), path);
+
+        let first = request.send().unwrap();
+        let key = request.preparer.full_url();
+        assert!(config.cache.as_ref().unwrap().get(&key).is_some());
+
+        let second = request.send().unwrap();
+        assert_eq!(first.body, second.body);
+        assert_eq!(first.etag_timestamp(), second.etag_timestamp());
+    }
+}
@@ -1,44 +1,117 @@
+use std::sync::Arc;
+
 use hyper::client;
+use hyper::client::pool::{Pool, Config as PoolConfig};
 use hyper::header::{Headers, Authorization, Basic};
 use hyper::net::HttpsConnector;
 use hyper_native_tls::NativeTlsClient;
 
+use auth::Auth;
+use cache::ResponseCache;
 use error::KintoError;
 use resource::Resource;
 use request::KintoRequest;
 use bucket::Bucket;
+use batch::BatchRequest;
+use retry::{RetryPolicy, BackoffState};
+use server_info::ServerInfo;
 
 use utils::unwrap_collection_records;
 
+#[cfg(feature = "async")]
+use async_client::{AsyncHttpClient, build_async_http_client};
+
 /// Configuration of the Kinto endpoint.
+///
+/// The HTTP client (and its keep-alive connection pool) is shared by every
+/// clone of the config, hence by every resource created from it.
 #[derive(Debug, Clone)]
 pub struct KintoConfig {
     pub server_url: String,
-    pub auth: Option<Authorization<Basic>>,
+    pub auth: Option<Arc<dyn Auth>>,
+    pub retry_policy: RetryPolicy,
+    /// Cache of read responses, revalidated with `If-None-Match`.
+    pub cache: Option<Arc<dyn ResponseCache>>,
+    backoff: BackoffState,
+    http_client: Arc<client::Client>,
+    #[cfg(feature = "async")]
+    async_http_client: Arc<AsyncHttpClient>,
 }
 
 impl KintoConfig {
     pub fn new(server_url: String, auth: Option<Authorization<Basic>>) -> KintoConfig {
+        let auth = auth.map(|auth| Arc::new(auth) as Arc<dyn Auth>);
+        KintoConfig::with_http_client(server_url, auth, KintoConfig::default_http_client())
+    }
+
+    /// Create a config using a custom authentication method.
+    pub fn with_auth<A>(server_url: String, auth: A) -> KintoConfig
+        where A: Auth + 'static
+    {
+        KintoConfig::with_http_client(server_url,
+                                      Some(Arc::new(auth)),
+                                      KintoConfig::default_http_client())
+    }
+
+    /// Create a config using an existing HTTP client.
+    pub fn with_http_client(server_url: String,
+                            auth: Option<Arc<dyn Auth>>,
+                            http_client: Arc<client::Client>)
+                            -> KintoConfig {
         KintoConfig {
             server_url: server_url,
             auth: auth,
+            retry_policy: RetryPolicy::default(),
+            cache: None,
+            backoff: BackoffState::default(),
+            http_client,
+            #[cfg(feature = "async")]
+            async_http_client: Arc::new(build_async_http_client()),
         }
     }
 
-    pub fn http_client(&self) -> client::Client {
+    /// Cache read responses (e.g. with a `MemoryCache` or a `FileCache`).
+    pub fn with_cache<C>(mut self, cache: C) -> KintoConfig
+        where C: ResponseCache + 'static
+    {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Build a pooled HTTP client with TLS support.
+    pub fn default_http_client() -> Arc<client::Client> {
         // Build an SSL connector
         let ssl = NativeTlsClient::new().unwrap();
         let connector = HttpsConnector::new(ssl);
 
-        // Build a HTTP Client with TLS support.
-        client::Client::with_connector(connector)
+        // Keep connections alive between requests.
+        let pool = Pool::with_connector(PoolConfig::default(), connector);
+
+        Arc::new(client::Client::with_connector(pool))
+    }
+
+    /// Get the shared HTTP client.
+    pub fn http_client(&self) -> Arc<client::Client> {
+        self.http_client.clone()
+    }
+
+    /// Get the backoff state requested by the server, shared by every clone
+    /// of the config.
+    pub fn backoff(&self) -> BackoffState {
+        self.backoff.clone()
+    }
+
+    /// Get the shared asynchronous HTTP client.
+    #[cfg(feature = "async")]
+    pub fn async_http_client(&self) -> Arc<AsyncHttpClient> {
+        self.async_http_client.clone()
     }
 }
 
 /// Client for the Kinto HTTP API.
 #[derive(Debug)]
 pub struct KintoClient {
-    pub http_client: client::Client,
+    pub http_client: Arc<client::Client>,
     config: KintoConfig,
 }
 
@@ -77,12 +150,25 @@ impl KintoClient {
         Ok(())
     }
 
+    /// Get the server version, settings, authenticated user and capabilities.
+    pub fn server_info(&self) -> Result<ServerInfo, KintoError> {
+        ServerInfo::fetch(self.config.clone())
+    }
+
+    /// Create a batch request sized to the server `batch_max_requests`.
+    pub fn new_batch(&self) -> Result<BatchRequest, KintoError> {
+        let info = try!(self.server_info());
+        let mut batch = BatchRequest::new(self.config.clone());
+        batch.limit_from(&info);
+        Ok(batch)
+    }
+
     /// Flush the server (if the flush endpoint is enabled).
     pub fn flush(&self) -> Result<(), KintoError> {
         // Set authentication headers
         let mut headers = Headers::new();
-        if let Some(ref method) = self.config.auth {
-            headers.set(method.clone());
+        if let Some(ref auth) = self.config.auth {
+            auth.apply(&mut headers);
         }
 
         try!(self.http_client
@@ -95,6 +181,8 @@ impl KintoClient {
 
 #[cfg(test)]
 mod test_client {
+    use std::sync::Arc;
+
     use resource::Resource;
     use utils::tests::setup_client;
 
@@ -113,4 +201,21 @@ mod test_client {
         assert_eq!(bucket.data, None);
         assert_eq!(bucket.get_id(), None);
     }
+
+    #[test]
+    fn test_server_info() {
+        let client = setup_client();
+        let info = client.server_info().unwrap();
+        assert_eq!(info.project_name, "kinto");
+        assert!(info.has_capability("flush"));
+        assert!(info.settings.batch_max_requests.is_some());
+        assert!(info.user.unwrap().id.starts_with("basicauth:"));
+    }
+
+    #[test]
+    fn test_resources_share_http_client() {
+        let client = setup_client();
+        let record = client.bucket("food").collection("meat").record("entrecote");
+        assert!(Arc::ptr_eq(&client.http_client, &record.get_config().http_client()));
+    }
 }
@@ -1,14 +1,28 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json;
 use serde_json::Value;
 
 use KintoConfig;
 use error::KintoError;
-use request::KintoRequest;
+use request::{KintoRequest, PluralEndpoint, GetCollection, HeadCollection};
 use response::ResponseWrapper;
 use resource::Resource;
 use bucket::Bucket;
 use record::Record;
-use utils::unwrap_collection_records;
+use batch::RecordBatch;
+use history::HistoryEntry;
+use server_info::ServerInfo;
+use signer::{SignerAction, SignerStatus, SignerCapability, SignerResource,
+             SignedCollections};
+#[cfg(feature = "signature")]
+use signature::{SignatureMetadata, SignatureVerifier};
+use paginator::Paginator;
+use typed::TypedCollection;
+use utils::{unwrap_collection_records, unwrap_permissions, collection_payload};
+use utils::sha256_hex;
 
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -22,6 +36,155 @@ pub struct CollectionPermissions {
 }
 
 
+/// Records changed on a collection since a given timestamp.
+#[derive(Debug, Clone)]
+pub struct ChangeSet {
+    /// Records created or updated since the timestamp.
+    pub changed: Vec<Record>,
+    /// Tombstones (`deleted: true`) of the records deleted since the timestamp.
+    pub deleted: Vec<Record>,
+    /// Collection timestamp to persist and use for the next sync.
+    pub timestamp: Option<u64>,
+}
+
+
+impl ChangeSet {
+    /// Split changed records between created and updated ones, given the ids
+    /// already known by the caller. Kinto does not expose creation dates, so
+    /// this can not be done on the server side.
+    pub fn split_created(&self, known_ids: &[String]) -> (Vec<Record>, Vec<Record>) {
+        self.changed
+            .iter()
+            .cloned()
+            .partition(|record| match record.get_id() {
+                           Some(id) => !known_ids.contains(&id),
+                           None => true,
+                       })
+    }
+}
+
+
+/// Record operations restoring a collection to a past state.
+#[derive(Debug, Clone)]
+pub struct RestorePlan {
+    /// Records deleted since the timestamp, to create again.
+    pub recreated: Vec<Record>,
+    /// Records modified since the timestamp, with their past data.
+    pub reverted: Vec<Record>,
+    /// Records created since the timestamp, to delete.
+    pub deleted: Vec<Record>,
+    /// Ids of the records changed since the timestamp whose past state is not
+    /// in the history (e.g. older than the plugin activation), left untouched.
+    pub unrestorable: Vec<String>,
+}
+
+
+impl RestorePlan {
+    /// Compute the plan from the current records and the records history.
+    ///
+    /// Records changed since the timestamp are restored to their last
+    /// version at that time, found in the history. Records without history
+    /// before the timestamp are only deleted if their first change after it
+    /// is a creation, and are reported as unrestorable otherwise.
+    pub fn from_history(collection: &Collection,
+                        timestamp: u64,
+                        current: &[Record],
+                        history: &[HistoryEntry])
+                        -> RestorePlan {
+        let mut first_change: HashMap<String, &HistoryEntry> = HashMap::new();
+        let mut past: HashMap<String, &HistoryEntry> = HashMap::new();
+
+        for entry in history {
+            let id = match entry.record_id {
+                Some(ref id) => id.to_owned(),
+                None => continue,
+            };
+            if entry.last_modified > timestamp {
+                let older = match first_change.get(&id) {
+                    Some(known) => entry.last_modified < known.last_modified,
+                    None => true,
+                };
+                if older {
+                    first_change.insert(id, entry);
+                }
+                continue;
+            }
+            let newer = match past.get(&id) {
+                Some(known) => entry.last_modified > known.last_modified,
+                None => true,
+            };
+            if newer {
+                past.insert(id, entry);
+            }
+        }
+
+        let current: HashMap<String, &Record> = current
+            .iter()
+            .filter_map(|record| record.get_id().map(|id| (id, record)))
+            .collect();
+
+        let mut plan = RestorePlan {
+            recreated: vec![],
+            reverted: vec![],
+            deleted: vec![],
+            unrestorable: vec![],
+        };
+
+        let mut ids: Vec<String> = first_change.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            // The record did not exist at the timestamp if it was deleted
+            // before, or if it was created after.
+            let past_data = match past.get(&id) {
+                Some(entry) if entry.action == "delete" => None,
+                Some(entry) => Some(without_metadata(&entry.target["data"])),
+                None if first_change[&id].action == "create" => None,
+                None => {
+                    plan.unrestorable.push(id);
+                    continue;
+                }
+            };
+            let current_record = current.get(&id);
+
+            match (past_data, current_record) {
+                (Some(data), None) => {
+                    let mut record = collection.record(&id);
+                    record.data = data.into();
+                    plan.recreated.push(record);
+                }
+                (Some(data), Some(record)) => {
+                    let current_data = record.data.as_ref().map(without_metadata);
+                    if current_data.as_ref() != Some(&data) {
+                        let mut record = collection.record(&id);
+                        record.data = data.into();
+                        plan.reverted.push(record);
+                    }
+                }
+                (None, Some(record)) => plan.deleted.push((*record).clone()),
+                (None, None) => (),
+            }
+        }
+        plan
+    }
+
+    /// Whether the collection is already in the past state.
+    pub fn is_empty(&self) -> bool {
+        self.recreated.is_empty() && self.reverted.is_empty() &&
+        self.deleted.is_empty() && self.unrestorable.is_empty()
+    }
+}
+
+
+/// Copy record data without the server managed timestamp.
+fn without_metadata(data: &Value) -> Value {
+    let mut data = data.to_owned();
+    if let Some(object) = data.as_object_mut() {
+        object.remove("last_modified");
+    }
+    data
+}
+
+
 #[derive(Debug, Clone)]
 pub struct Collection {
     pub data: Option<Value>,
@@ -67,6 +230,281 @@ impl Collection {
         Ok(unwrap_collection_records(&response, &self.new_record()))
     }
 
+    /// Count the records without downloading them.
+    pub fn count_records(&self) -> Result<u64, KintoError> {
+        try!(self.new_record().count_request()).count()
+    }
+
+    /// Count the records matching the filters set on the request.
+    pub fn count_records_with<F>(&self, filter: F) -> Result<u64, KintoError>
+        where F: FnOnce(&mut HeadCollection)
+    {
+        let mut request = try!(self.new_record().count_request());
+        filter(&mut request);
+        request.count()
+    }
+
+    /// Get a view of the collection whose records are converted to `T`.
+    pub fn typed<T>(&self) -> TypedCollection<T>
+        where T: Serialize + DeserializeOwned + Clone
+    {
+        TypedCollection::new(self.clone())
+    }
+
+    /// Iterate over all records, fetching pages lazily.
+    pub fn iter_records(&self) -> Result<Paginator<Record, GetCollection>, KintoError> {
+        Ok(Paginator::new(try!(self.new_record().list_request()), self.new_record()))
+    }
+
+    /// Get the records created, updated or deleted after the given timestamp.
+    pub fn changes_since(&self, timestamp: u64) -> Result<ChangeSet, KintoError> {
+        let response = try!(try!(self.new_record().list_request())
+                                .since(timestamp)
+                                .follow_subrequests());
+
+        let (deleted, changed) = unwrap_collection_records(&response, &self.new_record())
+            .into_iter()
+            .partition(|record| match record.data {
+                           Some(ref data) => data["deleted"].as_bool().unwrap_or(false),
+                           None => false,
+                       });
+
+        Ok(ChangeSet {
+               changed,
+               deleted,
+               timestamp: response.etag_timestamp().or(Some(timestamp)),
+           })
+    }
+
+    /// Iterate over the changes of the collection and its records, most
+    /// recent first (history plugin).
+    pub fn history(&self) -> Result<Paginator<HistoryEntry, GetCollection>, KintoError> {
+        let id = match self.get_id() {
+            Some(id) => id,
+            None => return Err(KintoError::UndefinedIdError),
+        };
+        let mut history = try!(self.bucket.history());
+        history.request.eq("collection_id", &id);
+        Ok(history)
+    }
+
+    /// Restore the records to their state at the given timestamp, using the
+    /// history plugin.
+    ///
+    /// Deleted records are created again, modified ones reverted and newer
+    /// ones deleted, through a chunked batch. Records changed meanwhile are
+    /// not overwritten and fail with `KintoError::Conflict`. With `dry_run`,
+    /// the plan is only computed.
+    pub fn restore_to(&self,
+                      timestamp: u64,
+                      dry_run: bool)
+                      -> Result<RestorePlan, KintoError> {
+        let current = try!(self.list_records());
+
+        let mut history = try!(self.history());
+        history.request.eq("resource_name", "record");
+        let mut entries = vec![];
+        for entry in history {
+            entries.push(try!(entry));
+        }
+
+        let plan = RestorePlan::from_history(self, timestamp, &current, &entries);
+        if dry_run || plan.is_empty() {
+            return Ok(plan);
+        }
+
+        let mut timestamps = HashMap::new();
+        for record in current.iter() {
+            if let Some(id) = record.get_id() {
+                let timestamp = match record.get_timestamp() {
+                    Some(timestamp) => timestamp,
+                    None => return Err(KintoError::UndefinedTimestampError),
+                };
+                timestamps.insert(id, timestamp);
+            }
+        }
+
+        let results = try!(self.batch(|batch| {
+            for record in plan.recreated.iter() {
+                batch.create(record.clone());
+            }
+            for record in plan.reverted.iter() {
+                let id = record.get_id().unwrap_or_default();
+                batch.update_if_unchanged(record.clone(), timestamps[&id]);
+            }
+            for record in plan.deleted.iter() {
+                let id = record.get_id().unwrap_or_default();
+                batch.delete_if_unchanged(&id, timestamps[&id]);
+            }
+        }));
+        for result in results {
+            try!(result);
+        }
+        Ok(plan)
+    }
+
+    /// Get the review status of the collection (kinto-signer plugin).
+    pub fn signer_status(&self) -> Option<SignerStatus> {
+        match self.data {
+            Some(ref data) => serde_json::from_value(data["status"].to_owned()).ok(),
+            None => None,
+        }
+    }
+
+    /// Ask the reviewers to approve the changes, as an editor.
+    pub fn request_review(&mut self, comment: &str) -> Result<(), KintoError> {
+        let changes = json!({"last_editor_comment": comment});
+        self.set_signer_status(SignerAction::RequestReview, changes)
+    }
+
+    /// Approve the changes under review, which are then signed, as a
+    /// reviewer.
+    pub fn approve(&mut self) -> Result<(), KintoError> {
+        self.set_signer_status(SignerAction::Approve, json!({}))
+    }
+
+    /// Send the changes under review back to the editors, as a reviewer.
+    pub fn decline(&mut self, comment: &str) -> Result<(), KintoError> {
+        let changes = json!({"last_reviewer_comment": comment});
+        self.set_signer_status(SignerAction::Decline, changes)
+    }
+
+    /// Discard the changes made since the last signature.
+    pub fn rollback(&mut self) -> Result<(), KintoError> {
+        self.set_signer_status(SignerAction::Rollback, json!({}))
+    }
+
+    /// Patch the status of the source collection.
+    ///
+    /// The user groups are first checked against the server `signer`
+    /// capability, failing with `KintoError::SignerError`, as does a status
+    /// transition refused by the server.
+    /// As the status is sent with `If-Match`, a concurrent change fails with
+    /// `KintoError::Conflict`.
+    fn set_signer_status(&mut self,
+                         action: SignerAction,
+                         mut changes: Value)
+                         -> Result<(), KintoError> {
+        let (info, capability, resource) = try!(self.signer_resource());
+        let resource = match resource {
+            Some(resource) => resource,
+            None => {
+                let message = "Collection is not signed".to_owned();
+                return Err(KintoError::SignerError(message));
+            }
+        };
+        if Some(resource.source.bucket.clone()) != self.bucket.get_id() ||
+           resource.source.collection != self.get_id() {
+            let message = "Review steps are taken on the source collection".to_owned();
+            return Err(KintoError::SignerError(message));
+        }
+
+        if self.get_timestamp().is_none() {
+            try!(self.load());
+        }
+        let data = self.data.clone().unwrap_or_default();
+        try!(capability.check_action(&resource, action, &data, info.user.as_ref()));
+
+        changes["status"] = action.status().as_str().into();
+        match self.patch(changes) {
+            Err(KintoError::ServerError { status, ref response })
+                if status.is_client_error() => {
+                Err(KintoError::SignerError(response.message.clone()))
+            }
+            result => result,
+        }
+    }
+
+    /// Get the server info, its `signer` capability and the signed resource
+    /// of the collection, if any.
+    fn signer_resource(&self)
+                       -> Result<(ServerInfo, SignerCapability, Option<SignerResource>),
+                                 KintoError> {
+        let (bucket_id, id) = match (self.bucket.get_id(), self.get_id()) {
+            (Some(bucket_id), Some(id)) => (bucket_id, id),
+            _ => return Err(KintoError::UndefinedIdError),
+        };
+
+        let info = try!(ServerInfo::fetch(self.get_config()));
+        let capability = match SignerCapability::from_server_info(&info) {
+            Some(capability) => capability,
+            None => return Err(KintoError::UnavailableEndpointError),
+        };
+        let resource = capability.resource(&bucket_id, &id);
+        Ok((info, capability, resource))
+    }
+
+    /// Get the source, preview and destination collections of a signed
+    /// collection, from the server `signer` capability.
+    ///
+    /// Return `None` if the collection is not signed, and fails with
+    /// `KintoError::UnavailableEndpointError` if the plugin is not enabled.
+    pub fn signed_collections(&self) -> Result<Option<SignedCollections>, KintoError> {
+        let (_, capability, resource) = try!(self.signer_resource());
+        let config = self.get_config();
+        Ok(resource.map(|resource| {
+                            SignedCollections::new(config, &capability, &resource)
+                        }))
+    }
+
+    /// Verify the kinto-signer Content-Signature of the collection records.
+    ///
+    /// The collection metadata is reloaded to get the current signature.
+    #[cfg(feature = "signature")]
+    pub fn verify_signature(&self,
+                            verifier: &SignatureVerifier)
+                            -> Result<(), KintoError> {
+        let mut collection = self.clone();
+        try!(collection.load());
+        let data = collection.data.as_ref();
+        let metadata = match data.and_then(SignatureMetadata::from_data) {
+            Some(metadata) => metadata,
+            None => {
+                let message = "collection is not signed".to_owned();
+                return Err(KintoError::SignatureError(message));
+            }
+        };
+
+        let payload = try!(self.payload());
+        verifier.verify(&self.get_config(), payload.as_bytes(), &metadata)
+    }
+
+    /// Get the hex encoded SHA-256 of the collection records and timestamp.
+    ///
+    /// The records are serialized as signed by kinto-signer, so the checksum
+    /// can be compared with one computed by the server.
+    pub fn checksum(&self) -> Result<String, KintoError> {
+        let payload = try!(self.payload());
+        Ok(sha256_hex(payload.as_bytes()))
+    }
+
+    /// Serialize the current records and timestamp in canonical JSON.
+    fn payload(&self) -> Result<String, KintoError> {
+        let response = try!(try!(self.new_record().list_request()).follow_subrequests());
+        let timestamp = match response.etag_timestamp() {
+            Some(timestamp) => timestamp,
+            None => {
+                let message = "missing records timestamp".to_owned();
+                return Err(KintoError::InvalidResponseError(message));
+            }
+        };
+        let records = response.body["data"].as_array().cloned().unwrap_or_default();
+        Ok(collection_payload(&records, timestamp))
+    }
+
+    /// Send record operations in a single batch.
+    ///
+    /// The records are returned in order, updated with their response.
+    pub fn batch<F>(&self,
+                    build: F)
+                    -> Result<Vec<Result<Record, KintoError>>, KintoError>
+        where F: FnOnce(&mut RecordBatch)
+    {
+        let mut batch = RecordBatch::new(self.clone());
+        build(&mut batch);
+        batch.send()
+    }
+
     /// Delete all available records.
     pub fn delete_records(&self) -> Result<(), KintoError> {
         let resource = Record::new(self.clone());
@@ -82,9 +520,8 @@ impl Resource for Collection {
     }
 
     fn unwrap_response(&mut self, wrapper: ResponseWrapper) {
-        self.data = Some(wrapper.body["data"].to_owned());
-        self.permissions = serde_json::from_value(wrapper.body["permissions"].to_owned())
-            .unwrap();
+        self.data = Some(wrapper.data());
+        self.permissions = unwrap_permissions(&wrapper);
         self.id = Some(wrapper.body["data"]["id"].as_str().unwrap().to_owned());
     }
 
@@ -137,11 +574,125 @@ impl Resource for Collection {
 
 #[cfg(test)]
 mod test_collection {
+    use collection::RestorePlan;
+    use history::HistoryEntry;
+    use signer::SignerStatus;
     use request::{KintoRequest, PluralEndpoint};
     use resource::Resource;
     use record::Record;
+    use bucket::Bucket;
     use utils::unwrap_collection_records;
-    use utils::tests::{setup_collection, setup_bucket};
+    use utils::tests::{setup_config, setup_collection, setup_bucket};
+
+    fn history_entry(id: &str, action: &str, timestamp: u64, price: u32) -> HistoryEntry {
+        HistoryEntry {
+            last_modified: timestamp,
+            action: action.to_owned(),
+            resource_name: "record".to_owned(),
+            record_id: Some(id.to_owned()),
+            target: json!({
+                "data": {"id": id, "last_modified": timestamp, "price": price},
+            }),
+            ..HistoryEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_signer_status() {
+        let mut collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        assert_eq!(collection.signer_status(), None);
+
+        collection.data = json!({"status": "to-review"}).into();
+        assert_eq!(collection.signer_status(), Some(SignerStatus::ToReview));
+
+        collection.data = json!({"status": "unknown"}).into();
+        assert_eq!(collection.signer_status(), None);
+    }
+
+    #[test]
+    fn test_restore_plan_from_history() {
+        let collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        let history = vec![history_entry("new", "create", 40, 1),
+                           history_entry("changed", "update", 30, 25),
+                           history_entry("removed", "delete", 25, 0),
+                           history_entry("unchanged", "create", 12, 5),
+                           history_entry("removed", "create", 11, 8),
+                           history_entry("changed", "create", 10, 20)];
+
+        let mut current = vec![];
+        for &(id, price) in [("new", 1), ("changed", 25), ("unchanged", 5)].iter() {
+            let mut record = collection.record(id);
+            record.data = json!({"id": id, "price": price}).into();
+            current.push(record);
+        }
+
+        let plan = RestorePlan::from_history(&collection, 20, &current, &history);
+        assert_eq!(plan.recreated.len(), 1);
+        assert_eq!(plan.recreated[0].data.clone().unwrap(),
+                   json!({"id": "removed", "price": 8}));
+        assert_eq!(plan.reverted.len(), 1);
+        assert_eq!(plan.reverted[0].data.clone().unwrap()["price"], 20);
+        assert_eq!(plan.deleted.len(), 1);
+        assert_eq!(plan.deleted[0].get_id().unwrap(), "new");
+
+        let plan = RestorePlan::from_history(&collection, 40, &current, &history);
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn test_restore_plan_without_past_history() {
+        let collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        let history = vec![history_entry("legacy", "update", 30, 25),
+                           history_entry("legacy-removed", "delete", 30, 0),
+                           history_entry("recreated", "create", 30, 3),
+                           history_entry("recreated", "delete", 15, 0),
+                           history_entry("recreated", "create", 10, 2)];
+
+        let mut current = vec![];
+        for &(id, price) in [("legacy", 25), ("recreated", 3)].iter() {
+            let mut record = collection.record(id);
+            record.data = json!({"id": id, "price": price}).into();
+            current.push(record);
+        }
+
+        let plan = RestorePlan::from_history(&collection, 20, &current, &history);
+        assert_eq!(plan.unrestorable, vec!["legacy", "legacy-removed"]);
+        assert_eq!(plan.deleted.len(), 1);
+        assert_eq!(plan.deleted[0].get_id().unwrap(), "recreated");
+        assert!(plan.recreated.is_empty());
+        assert!(plan.reverted.is_empty());
+        assert!(!plan.is_empty());
+    }
+
+    #[test]
+    fn test_restore_collection() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut changed = collection.record("changed");
+        changed.data = json!({"price": 20}).into();
+        changed.create().unwrap();
+        let mut removed = collection.record("removed");
+        removed.data = json!({"price": 8}).into();
+        removed.create().unwrap();
+        let timestamp = removed.get_timestamp().unwrap();
+
+        changed.patch(json!({"price": 25})).unwrap();
+        removed.delete().unwrap();
+        collection.record("new").create().unwrap();
+
+        let plan = collection.restore_to(timestamp, true).unwrap();
+        assert_eq!(plan.recreated.len(), 1);
+        assert_eq!(plan.reverted.len(), 1);
+        assert_eq!(plan.deleted.len(), 1);
+        assert_eq!(collection.list_records().unwrap().len(), 2);
+
+        collection.restore_to(timestamp, false).unwrap();
+        changed.load().unwrap();
+        assert_eq!(changed.data.unwrap()["price"], 20);
+        removed.load().unwrap();
+        collection.record("new").load().unwrap_err();
+        assert!(collection.restore_to(timestamp, true).unwrap().is_empty());
+    }
 
     #[test]
     fn test_create_collection() {
@@ -238,6 +789,23 @@ mod test_collection {
         assert_eq!(records.unwrap().len(), 10);
     }
 
+    #[test]
+    fn test_count_records() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        for i in 0..5 {
+            let mut record = collection.new_record();
+            record.data = json!({"even": i % 2 == 0}).into();
+            record.create().unwrap();
+        }
+
+        assert_eq!(collection.count_records().unwrap(), 5);
+        let even = collection.count_records_with(|request| {
+                                                     request.eq("even", "true");
+                                                 });
+        assert_eq!(even.unwrap(), 3);
+    }
+
     #[test]
     fn test_paginated_records_list() {
         let mut collection = setup_collection();
@@ -258,6 +826,97 @@ mod test_collection {
         assert_eq!(records.len(), 10);
     }
 
+    #[test]
+    fn test_iter_records() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        for _ in 0..10 {
+            collection.new_record().create().unwrap();
+        }
+
+        let mut iterator = collection.iter_records().unwrap();
+        iterator.request.limit(3);
+        let records: Vec<Record> = iterator.map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 10);
+    }
+
+    #[test]
+    fn test_iter_records_resume() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        for _ in 0..10 {
+            collection.new_record().create().unwrap();
+        }
+
+        for &taken in [2, 4, 6].iter() {
+            let mut iterator = collection.iter_records().unwrap();
+            iterator.request.limit(4);
+            let first: Vec<Record> =
+                iterator.by_ref().take(taken).map(|r| r.unwrap()).collect();
+            let token = iterator.resume_token().unwrap();
+
+            let mut resumed = collection.iter_records().unwrap();
+            resumed.request.limit(4);
+            let resumed = resumed.resume_from(token);
+            let rest: Vec<Record> = resumed.map(|r| r.unwrap()).collect();
+
+            let mut ids: Vec<String> = first
+                .iter()
+                .chain(rest.iter())
+                .map(|record| record.get_id().unwrap())
+                .collect();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 10);
+        }
+
+        let mut iterator = collection.iter_records().unwrap();
+        assert!(iterator.by_ref().all(|r| r.is_ok()));
+        assert_eq!(iterator.resume_token(), None);
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut kept = collection.new_record();
+        kept.create().unwrap();
+        let mut removed = collection.new_record();
+        removed.create().unwrap();
+
+        let timestamp = collection.changes_since(0).unwrap().timestamp.unwrap();
+
+        kept.data = json!({"good": true}).into();
+        kept.set().unwrap();
+        removed.delete().unwrap();
+        let mut created = collection.new_record();
+        created.create().unwrap();
+
+        let changes = collection.changes_since(timestamp).unwrap();
+        assert_eq!(changes.changed.len(), 2);
+        assert_eq!(changes.deleted.len(), 1);
+        assert_eq!(changes.deleted[0].get_id(), removed.get_id());
+        assert!(changes.timestamp.unwrap() > timestamp);
+
+        let known = vec![kept.get_id().unwrap()];
+        let (new, updated) = changes.split_created(&known);
+        assert_eq!(new[0].get_id(), created.get_id());
+        assert_eq!(updated[0].get_id(), kept.get_id());
+    }
+
+    #[test]
+    fn test_changes_since_without_changes() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        collection.new_record().create().unwrap();
+
+        let timestamp = collection.changes_since(0).unwrap().timestamp.unwrap();
+        let changes = collection.changes_since(timestamp).unwrap();
+        assert_eq!(changes.changed.len(), 0);
+        assert_eq!(changes.deleted.len(), 0);
+        assert_eq!(changes.timestamp, Some(timestamp));
+    }
+
     #[test]
     fn test_delete_records() {
         let mut collection = setup_collection();
@@ -288,4 +947,17 @@ mod test_collection {
         let records = collection.list_records().unwrap();
         assert_eq!(records.len(), 0);
     }
+
+    #[test]
+    fn test_checksum() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        collection.record("a").set().unwrap();
+        let checksum = collection.checksum().unwrap();
+        assert_eq!(checksum.len(), 64);
+        assert_eq!(collection.checksum().unwrap(), checksum);
+
+        collection.record("b").set().unwrap();
+        assert!(collection.checksum().unwrap() != checksum);
+    }
 }
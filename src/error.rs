@@ -1,47 +1,365 @@
 extern crate hyper;
 extern crate serde;
 
+use std::error::Error;
+use std::fmt;
 use std::io::Error as IOError;
 use std::str::Utf8Error;
 use hyper::Error as HyperError;
+use hyper::status::StatusCode;
+use serde_json;
+use serde_json::Value;
 use serde_json::error::Error as JsonError;
 
+use response::ResponseWrapper;
+
+#[cfg(feature = "async")]
+use hyper_async::Error as AsyncHyperError;
+#[cfg(feature = "async")]
+use hyper_async::http::Error as HttpError;
+#[cfg(feature = "async")]
+use hyper_util::client::legacy::Error as AsyncClientError;
+#[cfg(feature = "signature")]
+use openssl::error::ErrorStack;
+
+
+/// Error body returned by the Kinto server on non successful responses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    #[serde(default)]
+    pub code: u16,
+    #[serde(default)]
+    pub errno: u16,
+    #[serde(default)]
+    pub error: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub details: Option<Value>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub info: Option<String>,
+}
+
+
+impl ErrorResponse {
+    /// Parse an error body, falling back to the raw text if it is not JSON.
+    pub fn from_body(status: StatusCode, body: &str) -> ErrorResponse {
+        match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(mut response) => {
+                if response.code == 0 {
+                    response.code = status.to_u16();
+                }
+                response
+            }
+            Err(_) => {
+                ErrorResponse {
+                    code: status.to_u16(),
+                    error: status
+                        .canonical_reason()
+                        .unwrap_or("Unknown Error")
+                        .to_owned(),
+                    message: body.to_owned(),
+                    ..ErrorResponse::default()
+                }
+            }
+        }
+    }
+}
+
 
 #[derive(Debug)]
 pub enum KintoError {
     NotModified,
-    PreconditionError,
+    /// The object was modified (or created) meanwhile on the server.
+    Conflict {
+        response: Box<ErrorResponse>,
+        /// Current server version of the object, if it still exists.
+        existing: Option<Box<Value>>,
+    },
+    ServerError {
+        status: StatusCode,
+        response: Box<ErrorResponse>,
+    },
+    /// A chunked batch failed after the server committed its first chunks.
+    PartialBatchError {
+        /// Merged `/batch` response of the committed chunks, whose
+        /// subresponses match the first requests.
+        response: Box<ResponseWrapper>,
+        /// Error of the first failed chunk.
+        error: Box<KintoError>,
+    },
     UndefinedIdError,
+    UndefinedTimestampError,
     UnavailableEndpointError,
-    HyperError,
-    JsonError,
-    IOError,
+    InvalidResponseError(String),
+    HyperError(HyperError),
+    JsonError(JsonError),
+    IOError(IOError),
+    Utf8Error(Utf8Error),
+    #[cfg(feature = "async")]
+    AsyncHyperError(AsyncHyperError),
+    #[cfg(feature = "async")]
+    HttpError(HttpError),
+    /// Connection failure of the asynchronous HTTP client.
+    #[cfg(feature = "async")]
+    AsyncClientError(AsyncClientError),
+    /// Review step not allowed by the kinto-signer workflow.
+    SignerError(String),
+    /// Content-Signature or certificate chain verification failure.
+    #[cfg(feature = "signature")]
+    SignatureError(String),
+}
+
+
+impl KintoError {
+    /// Build an error from an unsuccessful server response.
+    pub fn from_response(status: StatusCode, body: &str) -> KintoError {
+        let response = Box::new(ErrorResponse::from_body(status, body));
+        match status {
+            StatusCode::PreconditionFailed => {
+                let existing = response
+                    .details
+                    .as_ref()
+                    .map(|details| details["existing"].to_owned())
+                    .and_then(|existing| if existing.is_object() {
+                                  Some(Box::new(existing))
+                              } else {
+                                  None
+                              });
+                KintoError::Conflict {
+                    response,
+                    existing,
+                }
+            }
+            _ => {
+                KintoError::ServerError {
+                    status,
+                    response,
+                }
+            }
+        }
+    }
+
+    /// HTTP status of the server response, if the error comes from one.
+    pub fn status(&self) -> Option<StatusCode> {
+        match *self {
+            KintoError::NotModified => Some(StatusCode::NotModified),
+            KintoError::Conflict { .. } => Some(StatusCode::PreconditionFailed),
+            KintoError::ServerError { status, .. } => Some(status),
+            KintoError::PartialBatchError { ref error, .. } => error.status(),
+            _ => None,
+        }
+    }
+
+    /// Kinto error body, if the error comes from the server.
+    pub fn response(&self) -> Option<&ErrorResponse> {
+        match *self {
+            KintoError::Conflict { ref response, .. } => Some(response),
+            KintoError::ServerError { ref response, .. } => Some(response),
+            KintoError::PartialBatchError { ref error, .. } => error.response(),
+            _ => None,
+        }
+    }
+
+    /// Current server version of the object on conflicts, if it exists.
+    pub fn existing(&self) -> Option<&Value> {
+        match *self {
+            KintoError::Conflict { ref existing, .. } => existing.as_ref().map(|e| &**e),
+            _ => None,
+        }
+    }
+}
+
+
+impl fmt::Display for KintoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KintoError::NotModified => write!(f, "Resource not modified"),
+            KintoError::Conflict { ref response, .. } => {
+                write!(f, "Conflict: {}", response.message)
+            }
+            KintoError::ServerError {
+                status,
+                ref response,
+            } => {
+                write!(f,
+                       "Server error {} (errno {}): {}",
+                       status,
+                       response.errno,
+                       response.message)
+            }
+            KintoError::PartialBatchError {
+                ref response,
+                ref error,
+            } => {
+                let committed = response.body["responses"]
+                    .as_array()
+                    .map_or(0, |responses| responses.len());
+                write!(f, "Batch interrupted after {} requests: {}", committed, error)
+            }
+            KintoError::UndefinedIdError => write!(f, "Resource id is undefined"),
+            KintoError::UndefinedTimestampError => {
+                write!(f, "Resource timestamp is undefined")
+            }
+            KintoError::UnavailableEndpointError => write!(f, "Endpoint is unavailable"),
+            KintoError::InvalidResponseError(ref message) => {
+                write!(f, "Invalid server response: {}", message)
+            }
+            KintoError::HyperError(ref err) => write!(f, "HTTP error: {}", err),
+            KintoError::JsonError(ref err) => write!(f, "JSON error: {}", err),
+            KintoError::IOError(ref err) => write!(f, "IO error: {}", err),
+            KintoError::Utf8Error(ref err) => write!(f, "UTF-8 error: {}", err),
+            #[cfg(feature = "async")]
+            KintoError::AsyncHyperError(ref err) => write!(f, "HTTP error: {}", err),
+            #[cfg(feature = "async")]
+            KintoError::HttpError(ref err) => write!(f, "Invalid request: {}", err),
+            #[cfg(feature = "async")]
+            KintoError::AsyncClientError(ref err) => write!(f, "HTTP error: {}", err),
+            KintoError::SignerError(ref message) => {
+                write!(f, "Signer error: {}", message)
+            }
+            #[cfg(feature = "signature")]
+            KintoError::SignatureError(ref message) => {
+                write!(f, "Invalid signature: {}", message)
+            }
+        }
+    }
+}
+
+
+impl Error for KintoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            KintoError::HyperError(ref err) => Some(err),
+            KintoError::JsonError(ref err) => Some(err),
+            KintoError::IOError(ref err) => Some(err),
+            KintoError::Utf8Error(ref err) => Some(err),
+            KintoError::PartialBatchError { ref error, .. } => Some(&**error),
+            #[cfg(feature = "async")]
+            KintoError::AsyncHyperError(ref err) => Some(err),
+            #[cfg(feature = "async")]
+            KintoError::HttpError(ref err) => Some(err),
+            #[cfg(feature = "async")]
+            KintoError::AsyncClientError(ref err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 
 impl From<IOError> for KintoError {
     fn from(err: IOError) -> Self {
-        err.into()
+        KintoError::IOError(err)
     }
 }
 
 
 impl From<Utf8Error> for KintoError {
     fn from(err: Utf8Error) -> Self {
-        err.into()
+        KintoError::Utf8Error(err)
     }
 }
 
 
 impl From<JsonError> for KintoError {
     fn from(err: JsonError) -> Self {
-        err.into()
+        KintoError::JsonError(err)
     }
 }
 
 
 impl From<HyperError> for KintoError {
     fn from(err: HyperError) -> Self {
-        err.into()
+        KintoError::HyperError(err)
+    }
+}
+
+
+#[cfg(feature = "async")]
+impl From<AsyncHyperError> for KintoError {
+    fn from(err: AsyncHyperError) -> Self {
+        KintoError::AsyncHyperError(err)
+    }
+}
+
+
+#[cfg(feature = "async")]
+impl From<HttpError> for KintoError {
+    fn from(err: HttpError) -> Self {
+        KintoError::HttpError(err)
+    }
+}
+
+
+#[cfg(feature = "async")]
+impl From<AsyncClientError> for KintoError {
+    fn from(err: AsyncClientError) -> Self {
+        KintoError::AsyncClientError(err)
+    }
+}
+
+
+#[cfg(feature = "signature")]
+impl From<ErrorStack> for KintoError {
+    fn from(err: ErrorStack) -> Self {
+        KintoError::SignatureError(err.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod test_error {
+    use std::error::Error;
+    use hyper::status::StatusCode;
+
+    use super::KintoError;
+
+    #[test]
+    fn test_error_from_json_body() {
+        let body = r#"{"code": 403, "errno": 121, "error": "Forbidden",
+                       "message": "This user cannot access this resource."}"#;
+        let err = KintoError::from_response(StatusCode::Forbidden, body);
+        assert_eq!(err.status(), Some(StatusCode::Forbidden));
+        let response = err.response().unwrap();
+        assert_eq!(response.errno, 121);
+        assert_eq!(response.message, "This user cannot access this resource.");
+        assert_eq!(response.details, None);
+    }
+
+    #[test]
+    fn test_error_from_text_body() {
+        let err = KintoError::from_response(StatusCode::BadGateway, "<html></html>");
+        let response = err.response().unwrap();
+        assert_eq!(response.code, 502);
+        assert_eq!(response.error, "Bad Gateway");
+        assert_eq!(response.message, "<html></html>");
+    }
+
+    #[test]
+    fn test_precondition_failed_variant() {
+        let err = KintoError::from_response(StatusCode::PreconditionFailed, "{}");
+        match err {
+            KintoError::Conflict { ref response, .. } => assert_eq!(response.code, 412),
+            _ => panic!("Unexpected error {:?}", err),
+        }
+        assert_eq!(err.existing(), None);
+    }
+
+    #[test]
+    fn test_conflict_carries_existing_version() {
+        let body = r#"{"code": 412, "errno": 114, "error": "Precondition Failed",
+                       "message": "Resource was modified meanwhile",
+                       "details": {"existing": {"id": "a", "last_modified": 42}}}"#;
+        let err = KintoError::from_response(StatusCode::PreconditionFailed, body);
+        assert_eq!(err.status(), Some(StatusCode::PreconditionFailed));
+        assert_eq!(err.existing().unwrap()["last_modified"], 42);
+    }
+
+    #[test]
+    fn test_error_source() {
+        let json_err = ::serde_json::from_str::<u64>("nope").unwrap_err();
+        let err: KintoError = json_err.into();
+        assert!(err.source().is_some());
+        assert!(format!("{}", err).starts_with("JSON error"));
     }
 }
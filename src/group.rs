@@ -0,0 +1,261 @@
+use serde_json;
+use serde_json::Value;
+
+use KintoConfig;
+use error::KintoError;
+use response::ResponseWrapper;
+use resource::Resource;
+use bucket::Bucket;
+use utils::unwrap_permissions;
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupPermissions {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub read: Option<Vec<String>>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub write: Option<Vec<String>>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub data: Option<Value>,
+    pub permissions: GroupPermissions,
+    pub bucket: Bucket,
+    pub id: Option<String>,
+}
+
+
+impl Group {
+    /// Create a new group resource.
+    pub fn new(bucket: Bucket) -> Self {
+        Group {
+            bucket,
+            id: None,
+            data: None,
+            permissions: GroupPermissions::default(),
+        }
+    }
+
+    /// Create a new group resource with an id.
+    pub fn new_by_id(bucket: Bucket, id: &str) -> Self {
+        Group {
+            bucket,
+            id: Some(id.to_owned()),
+            data: None,
+            permissions: GroupPermissions::default(),
+        }
+    }
+
+    /// Get the principals currently stored on the local group data.
+    pub fn get_members(&self) -> Vec<String> {
+        match self.data {
+            Some(ref data) => {
+                match data["members"].as_array() {
+                    Some(members) => {
+                        members
+                            .iter()
+                            .filter_map(|m| m.as_str().map(|s| s.to_owned()))
+                            .collect()
+                    }
+                    None => vec![],
+                }
+            }
+            None => vec![],
+        }
+    }
+
+    /// Replace the members list on the local group data.
+    pub fn set_members(&mut self, members: Vec<String>) {
+        let mut data = self.data.clone().unwrap_or(json!({}));
+        data["members"] = members.into();
+        self.data = data.into();
+    }
+
+    /// Add principals to the group on the server.
+    ///
+    /// The group is reloaded and saved with an `If-Match` header, so that
+    /// a concurrent change fails with `KintoError::Conflict`
+    /// instead of being overwritten.
+    pub fn add_members(&mut self, principals: &[&str]) -> Result<(), KintoError> {
+        try!(self.load());
+
+        let mut members = self.get_members();
+        for principal in principals {
+            if !members.iter().any(|m| m == principal) {
+                members.push(principal.to_string());
+            }
+        }
+        self.set_members(members);
+        self.update()
+    }
+
+    /// Remove principals from the group on the server.
+    ///
+    /// Uses the same read-modify-write cycle as `add_members`.
+    pub fn remove_members(&mut self, principals: &[&str]) -> Result<(), KintoError> {
+        try!(self.load());
+
+        let members = self.get_members()
+            .into_iter()
+            .filter(|m| !principals.contains(&m.as_str()))
+            .collect();
+        self.set_members(members);
+        self.update()
+    }
+}
+
+
+impl Resource for Group {
+    fn resource_path(&self) -> Result<String, KintoError> {
+        Ok(format!("{}/groups", try!(self.bucket.record_path())))
+    }
+
+    fn unwrap_response(&mut self, wrapper: ResponseWrapper) {
+        self.data = Some(wrapper.data());
+        self.permissions = unwrap_permissions(&wrapper);
+        self.id = Some(wrapper.body["data"]["id"].as_str().unwrap().to_owned());
+    }
+
+    fn get_config(&self) -> KintoConfig {
+        self.bucket.get_config()
+    }
+
+    fn get_id(&self) -> Option<String> {
+        match self.id {
+            Some(ref id) => Some(id.clone()),
+
+            // If none, try to get id from body
+            None => {
+                match self.data {
+                    Some(ref data) => data["id"].as_str().map(|s| s.to_string()),
+                    None => None,
+                }
+            }
+        }
+    }
+
+    fn get_timestamp(&self) -> Option<u64> {
+        match self.get_data() {
+            Some(data) => {
+                match data["last_modified"].as_u64() {
+                    Some(ts) => ts.into(),
+                    None => None,
+                }
+            }
+            None => None,
+        }
+    }
+
+    fn get_data(&self) -> Option<Value> {
+        self.data.clone()
+    }
+
+    fn set_data(&mut self, data: Value) -> Self {
+        self.data = data.into();
+        self.clone()
+    }
+
+    fn get_permissions(&self) -> Option<Value> {
+        serde_json::to_value(&(self.permissions))
+            .unwrap_or_default()
+            .into()
+    }
+}
+
+
+#[cfg(test)]
+mod test_group {
+    use resource::Resource;
+    use utils::tests::{setup_group, setup_bucket};
+
+    #[test]
+    fn test_create_group() {
+        let mut group = setup_group();
+        group.data = json!({"members": ["alice"]}).into();
+
+        group.create().unwrap();
+        let data = group.data.unwrap().to_owned();
+
+        assert_eq!(data["id"], "editors");
+        assert_eq!(data["members"][0], "alice");
+    }
+
+    #[test]
+    fn test_create_group_fails_on_existing() {
+        let mut group = setup_group();
+
+        // Create
+        group.create().unwrap();
+
+        // Tries to create again
+        group.create().unwrap_err();
+    }
+
+    #[test]
+    fn test_load_group() {
+        let mut group = setup_group();
+        group.set().unwrap();
+        let create_data = group.data.clone().unwrap();
+
+        // Cleanup stored data to make sure load work
+        group.data = json!({}).into();
+
+        group.load().unwrap();
+        let load_data = group.data.unwrap();
+
+        assert_eq!(create_data, load_data);
+    }
+
+    #[test]
+    fn test_load_group_fails_on_not_existing() {
+        let mut group = setup_group();
+        group.load().unwrap_err();
+    }
+
+    #[test]
+    fn test_update_group_fails_on_not_existing() {
+        let bucket = setup_bucket();
+        let mut group = bucket.group("editors");
+        group.update().unwrap_err();
+    }
+
+    #[test]
+    fn test_add_members() {
+        let mut group = setup_group();
+        group.set_members(vec!["alice".to_owned()]);
+        group.create().unwrap();
+
+        group.add_members(&["bob", "alice"]).unwrap();
+        assert_eq!(group.get_members(), vec!["alice", "bob"]);
+
+        group.data = None;
+        group.load().unwrap();
+        assert_eq!(group.get_members(), vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_grant_group_permissions() {
+        let mut group = setup_group();
+        group.set_members(vec!["alice".to_owned()]);
+        group.create().unwrap();
+
+        group.grant("read", "bob").unwrap();
+        assert_eq!(group.permissions.read.clone().unwrap(), vec!["bob"]);
+        assert_eq!(group.get_members(), vec!["alice"]);
+
+        group.revoke("read", "bob").unwrap();
+        assert_eq!(group.permissions.read, None);
+    }
+
+    #[test]
+    fn test_remove_members() {
+        let mut group = setup_group();
+        group.set_members(vec!["alice".to_owned(), "bob".to_owned()]);
+        group.create().unwrap();
+
+        group.remove_members(&["alice"]).unwrap();
+        assert_eq!(group.get_members(), vec!["bob"]);
+    }
+}
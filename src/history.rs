@@ -0,0 +1,77 @@
+use serde_json;
+use serde_json::Value;
+
+use error::KintoError;
+use paginator::PageItem;
+
+
+/// Change recorded by the history plugin (`kinto.plugins.history`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub last_modified: u64,
+    /// Kind of change: "create", "update" or "delete".
+    pub action: String,
+    /// Kind of changed object: "bucket", "collection", "group" or "record".
+    pub resource_name: String,
+    pub uri: String,
+    pub user_id: String,
+    /// ISO 8601 date of the change.
+    #[serde(default)]
+    pub date: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub bucket_id: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub collection_id: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub group_id: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub record_id: Option<String>,
+    /// Data and permissions of the object after the change.
+    #[serde(default)]
+    pub target: Value,
+}
+
+
+impl PageItem for HistoryEntry {
+    fn with_page_data(&self, data: Value) -> Result<Self, KintoError> {
+        Ok(try!(serde_json::from_value(data)))
+    }
+}
+
+
+#[cfg(test)]
+mod test_history {
+    use history::HistoryEntry;
+    use paginator::PageItem;
+
+    #[test]
+    fn test_history_entry_with_page_data() {
+        let data = json!({
+            "id": "8a4a3d1c",
+            "last_modified": 1500000000000u64,
+            "action": "update",
+            "resource_name": "record",
+            "uri": "/buckets/food/collections/meat/records/entrecote",
+            "user_id": "account:alice",
+            "date": "2017-07-14T02:40:00.000000",
+            "bucket_id": "food",
+            "collection_id": "meat",
+            "record_id": "entrecote",
+            "target": {"data": {"id": "entrecote"}, "permissions": {}},
+        });
+
+        let entry = HistoryEntry::default().with_page_data(data).unwrap();
+        assert_eq!(entry.action, "update");
+        assert_eq!(entry.record_id.unwrap(), "entrecote");
+        assert_eq!(entry.group_id, None);
+        assert_eq!(entry.target["data"]["id"], "entrecote");
+    }
+
+    #[test]
+    fn test_invalid_history_entry() {
+        HistoryEntry::default()
+            .with_page_data(json!({"id": "a"}))
+            .unwrap_err();
+    }
+}
@@ -5,28 +5,78 @@ extern crate serde;
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate sha2;
+extern crate url;
+#[cfg(feature = "async")]
+extern crate bytes;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate http_body_util;
+#[cfg(feature = "async")]
+extern crate hyper_async;
+#[cfg(feature = "async")]
+extern crate hyper_tls;
+#[cfg(feature = "async")]
+extern crate hyper_util;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "signature")]
+extern crate foreign_types;
+#[cfg(feature = "signature")]
+extern crate openssl;
+#[cfg(feature = "signature")]
+extern crate openssl_sys;
 
+pub mod auth;
 pub mod client;
 pub mod paths;
 pub mod error;
 pub mod request;
 pub mod response;
+pub mod retry;
+pub mod cache;
+pub mod server_info;
 pub mod resource;
 pub mod batch;
+pub mod paginator;
+pub mod attachment;
+pub mod history;
+pub mod signer;
+#[cfg(feature = "signature")]
+pub mod signature;
+#[cfg(feature = "async")]
+pub mod async_client;
 
 pub mod bucket;
 pub mod collection;
+pub mod group;
 pub mod record;
+pub mod typed;
 
 pub mod utils;
 
+pub use auth::{Auth, TokenAuth};
 pub use error::KintoError;
 pub use client::KintoClient;
 pub use client::KintoConfig;
+pub use retry::RetryPolicy;
+pub use cache::{ResponseCache, MemoryCache, FileCache};
+pub use server_info::ServerInfo;
+#[cfg(feature = "async")]
+pub use async_client::{AsyncKintoClient, AsyncKintoRequest, AsyncResource};
 
 pub use bucket::Bucket;
 pub use collection::Collection;
+pub use group::Group;
 pub use record::Record;
+pub use typed::{TypedRecord, TypedCollection};
 
 pub use request::KintoRequest;
+pub use paginator::{Paginator, ResumeToken};
+pub use attachment::Attachment;
+pub use history::HistoryEntry;
+pub use signer::{SignerAction, SignerStatus, SignedCollections};
+#[cfg(feature = "signature")]
+pub use signature::SignatureVerifier;
 pub use resource::Resource;
@@ -0,0 +1,290 @@
+use std::cmp;
+use std::collections::VecDeque;
+
+use serde_json::Value;
+
+use error::KintoError;
+use request::KintoRequest;
+use resource::Resource;
+
+
+/// Object built from the data of a plural endpoint page.
+pub trait PageItem: Clone {
+    /// Build an item from an object of a page, using `self` as template.
+    fn with_page_data(&self, data: Value) -> Result<Self, KintoError>;
+}
+
+
+impl<T> PageItem for T
+    where T: Resource
+{
+    fn with_page_data(&self, data: Value) -> Result<Self, KintoError> {
+        Ok(self.clone().set_data(data))
+    }
+}
+
+
+/// Position of a paginated scan, to resume it later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumeToken {
+    /// Path of the page being consumed, including the pagination token, or
+    /// `None` for the first page.
+    pub page: Option<String>,
+    /// Number of objects of the page already consumed.
+    pub offset: usize,
+}
+
+
+/// Lazy iterator over the objects of a plural endpoint.
+///
+/// Pages are only fetched when the objects of the current one are consumed,
+/// following the `Next-Page` header sent by the server.
+#[derive(Debug, Clone)]
+pub struct Paginator<T, R>
+    where T: PageItem,
+          R: KintoRequest
+{
+    /// Request used for the first page, filters can be added to it.
+    pub request: R,
+    object: T,
+    buffer: VecDeque<Value>,
+    current_page: Option<String>,
+    offset: usize,
+    skip: usize,
+    next_page: Option<String>,
+    started: bool,
+    done: bool,
+}
+
+
+impl<T, R> Paginator<T, R>
+    where T: PageItem,
+          R: KintoRequest
+{
+    /// Create an iterator that sends `request` and wraps objects as `object`.
+    pub fn new(request: R, object: T) -> Paginator<T, R> {
+        Paginator {
+            request,
+            object,
+            buffer: VecDeque::new(),
+            current_page: None,
+            offset: 0,
+            skip: 0,
+            next_page: None,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Resume an interrupted scan from a token returned by `resume_token`.
+    ///
+    /// The page is fetched again and its consumed objects are skipped, so
+    /// changes on the server in the meantime may shift them.
+    pub fn resume_from(mut self, token: ResumeToken) -> Self {
+        self.started = token.page.is_some();
+        self.next_page = token.page;
+        self.skip = token.offset;
+        self
+    }
+
+    /// Position of the next object to return, or `None` once the scan is
+    /// complete.
+    ///
+    /// Store it to resume the scan later with `resume_from`.
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        if !self.buffer.is_empty() {
+            return Some(ResumeToken {
+                            page: self.current_page.clone(),
+                            offset: self.offset,
+                        });
+        }
+        if !self.started {
+            return Some(ResumeToken {
+                            page: None,
+                            offset: self.skip,
+                        });
+        }
+        if self.done {
+            return None;
+        }
+        self.next_page
+            .clone()
+            .map(|page| {
+                     ResumeToken {
+                         page: Some(page),
+                         offset: 0,
+                     }
+                 })
+    }
+
+    /// Fetch the next page into the buffer.
+    fn fetch_page(&mut self) -> Result<(), KintoError> {
+        let mut request = self.request.clone();
+        let mut page = None;
+
+        if self.started {
+            match self.next_page.clone() {
+                Some(path) => {
+                    request.preparer().path = path.clone();
+                    request.preparer().query = "".to_owned();
+                    page = Some(path);
+                }
+                None => {
+                    self.done = true;
+                    return Ok(());
+                }
+            }
+        }
+
+        let response = try!(request.send());
+        self.started = true;
+        self.current_page = page;
+        self.next_page = try!(response.next_page_path());
+
+        if let Some(data) = response.body["data"].as_array() {
+            // Skip the objects consumed before the scan was interrupted
+            let skip = cmp::min(self.skip, data.len());
+            self.buffer.extend(data.iter().skip(skip).cloned());
+            self.offset = skip;
+            self.skip = 0;
+        }
+        Ok(())
+    }
+}
+
+
+impl<T, R> Iterator for Paginator<T, R>
+    where T: PageItem,
+          R: KintoRequest
+{
+    type Item = Result<T, KintoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Pages can be empty, keep fetching until data or end of pagination
+        while self.buffer.is_empty() && !self.done {
+            if let Err(err) = self.fetch_page() {
+                self.done = true;
+                return Some(Err(err));
+            }
+            if self.next_page.is_none() {
+                self.done = true;
+            }
+        }
+
+        match self.buffer.pop_front() {
+            Some(data) => {
+                self.offset += 1;
+                Some(self.object.with_page_data(data))
+            }
+            None => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_paginator {
+    use hyper::header::Headers;
+    use hyper::status::StatusCode;
+    use serde_json::Value;
+
+    use error::KintoError;
+    use paginator::{Paginator, PageItem, ResumeToken};
+    use request::{KintoRequest, RequestPreparer};
+    use response::ResponseWrapper;
+    use utils::tests::setup_config;
+
+    /// Request serving 10 objects in pages of 4.
+    #[derive(Debug, Clone)]
+    struct PagesRequest {
+        preparer: RequestPreparer,
+    }
+
+    impl KintoRequest for PagesRequest {
+        fn preparer(&mut self) -> &mut RequestPreparer {
+            &mut self.preparer
+        }
+
+        fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
+            let config = self.preparer.config.clone();
+            let (start, next) = match self.preparer.path.as_str() {
+                "/items" => (0, Some("/items?_token=4")),
+                "/items?_token=4" => (4, Some("/items?_token=8")),
+                _ => (8, None),
+            };
+            let data: Vec<Value> = (start..::std::cmp::min(start + 4, 10))
+                .map(|i| json!({"id": i}))
+                .collect();
+
+            let mut headers = Headers::new();
+            if let Some(next) = next {
+                let url = format!("{}{}", config.server_url, next);
+                headers.set_raw("Next-Page", vec![url.into_bytes()]);
+            }
+            Ok(ResponseWrapper {
+                   config,
+                   path: self.preparer.path.clone(),
+                   status: StatusCode::Ok,
+                   headers,
+                   body: json!({"data": data}),
+               })
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Item(Value);
+
+    impl PageItem for Item {
+        fn with_page_data(&self, data: Value) -> Result<Self, KintoError> {
+            Ok(Item(data))
+        }
+    }
+
+    fn paginator() -> Paginator<Item, PagesRequest> {
+        let preparer = RequestPreparer::new(setup_config(), "/items".to_owned());
+        let request = PagesRequest { preparer };
+        Paginator::new(request, Item(Value::Null))
+    }
+
+    fn ids(paginator: &mut Paginator<Item, PagesRequest>, count: usize) -> Vec<u64> {
+        paginator
+            .take(count)
+            .map(|item| item.unwrap().0["id"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_in_the_middle_of_a_page() {
+        for taken in 0..10 {
+            let mut interrupted = paginator();
+            let mut all = ids(&mut interrupted, taken);
+            let token = interrupted.resume_token().unwrap();
+            all.extend(ids(&mut paginator().resume_from(token), 10));
+            assert_eq!(all, (0..10).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn test_resume_token() {
+        let mut paginator = paginator();
+        assert_eq!(paginator.resume_token(),
+                   Some(ResumeToken {
+                            page: None,
+                            offset: 0,
+                        }));
+        ids(&mut paginator, 6);
+        assert_eq!(paginator.resume_token(),
+                   Some(ResumeToken {
+                            page: Some("/items?_token=4".to_owned()),
+                            offset: 2,
+                        }));
+        ids(&mut paginator, 2);
+        assert_eq!(paginator.resume_token(),
+                   Some(ResumeToken {
+                            page: Some("/items?_token=8".to_owned()),
+                            offset: 0,
+                        }));
+        ids(&mut paginator, 10);
+        assert_eq!(paginator.resume_token(), None);
+    }
+}
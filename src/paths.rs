@@ -3,6 +3,7 @@ use std::convert::Into;
 
 /// Known paths in the kinto server.
 pub enum Paths<'a> {
+    Root,
     Batch,
     Buckets,
     Bucket(&'a str),
@@ -18,6 +19,7 @@ pub enum Paths<'a> {
 impl<'a> Into<String> for Paths<'a> {
     fn into(self) -> String {
         match self {
+            Paths::Root => "/".to_owned(),
             Paths::Batch => "/batch".to_owned(),
             Paths::Buckets => "/buckets".to_owned(),
             Paths::Bucket(id) => format!("/buckets/{id}", id = id),
@@ -57,6 +59,12 @@ impl<'a> Into<String> for Paths<'a> {
 mod test_paths {
     use super::Paths;
 
+    #[test]
+    fn test_root_path() {
+        let path: String = Paths::Root.into();
+        assert_eq!(path, "/");
+    }
+
     #[test]
     fn test_batch_path() {
         let path: String = Paths::Batch.into();
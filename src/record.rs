@@ -1,11 +1,21 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
 use serde_json;
 use serde_json::Value;
 
 use KintoConfig;
+use attachment::Attachment;
 use error::KintoError;
+use request::{KintoRequest, PluralEndpoint, GetCollection, UploadAttachment,
+              DeleteRecord};
 use response::ResponseWrapper;
 use resource::Resource;
 use collection::Collection;
+use history::HistoryEntry;
+use paginator::Paginator;
+use utils::unwrap_permissions;
 
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -46,6 +56,100 @@ impl Record {
             id: Some(id.to_owned()),
         }
     }
+
+    /// Iterate over the changes of the record, most recent first (history
+    /// plugin).
+    pub fn history(&self) -> Result<Paginator<HistoryEntry, GetCollection>, KintoError> {
+        let id = match self.get_id() {
+            Some(id) => id,
+            None => return Err(KintoError::UndefinedIdError),
+        };
+        let collection_id = match self.collection.get_id() {
+            Some(id) => id,
+            None => return Err(KintoError::UndefinedIdError),
+        };
+
+        let mut history = try!(self.collection.bucket.history());
+        history
+            .request
+            .eq("resource_name", "record")
+            .eq("collection_id", &collection_id)
+            .eq("record_id", &id);
+        Ok(history)
+    }
+
+    /// Get the attachment endpoint path (kinto-attachment plugin).
+    fn attachment_path(&self) -> Result<String, KintoError> {
+        Ok(format!("{}/attachment", try!(self.record_path())))
+    }
+
+    /// Get the attachment described by the local record data, if any.
+    pub fn attachment(&self) -> Option<Attachment> {
+        match self.data {
+            Some(ref data) => Attachment::from_data(data),
+            None => None,
+        }
+    }
+
+    /// Upload a file as the record attachment, creating the record if needed.
+    ///
+    /// The record is reloaded afterwards, as the upload changes its data.
+    pub fn upload_attachment<P>(&mut self,
+                                path: P,
+                                mimetype: &str)
+                                -> Result<Attachment, KintoError>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let filename = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "attachment".to_owned(),
+        };
+        let file = try!(File::open(path));
+        self.upload_attachment_from(file, &filename, mimetype)
+    }
+
+    /// Upload the content of a reader as the record attachment.
+    pub fn upload_attachment_from<R>(&mut self,
+                                     mut reader: R,
+                                     filename: &str,
+                                     mimetype: &str)
+                                     -> Result<Attachment, KintoError>
+        where R: Read
+    {
+        let mut content = vec![];
+        try!(reader.read_to_end(&mut content));
+
+        let path = try!(self.attachment_path());
+        let response = try!(UploadAttachment::new(self.get_config(), path)
+                                .file(filename, mimetype, &content)
+                                .send());
+        let attachment = try!(serde_json::from_value(response.body));
+
+        try!(self.load());
+        Ok(attachment)
+    }
+
+    /// Delete the record attachment, keeping the record.
+    pub fn delete_attachment(&mut self) -> Result<(), KintoError> {
+        let path = try!(self.attachment_path());
+        try!(DeleteRecord::new(self.get_config(), path).send());
+        self.load()
+    }
+
+    /// Download the record attachment and check its hash.
+    ///
+    /// Fails with `KintoError::InvalidResponseError` if the record has no
+    /// attachment or if the downloaded file does not match it.
+    pub fn download_attachment(&self) -> Result<Vec<u8>, KintoError> {
+        match self.attachment() {
+            Some(attachment) => attachment.download(&self.get_config()),
+            None => {
+                let message = "record has no attachment".to_owned();
+                Err(KintoError::InvalidResponseError(message))
+            }
+        }
+    }
 }
 
 
@@ -55,9 +159,8 @@ impl Resource for Record {
     }
 
     fn unwrap_response(&mut self, wrapper: ResponseWrapper) {
-        self.data = Some(wrapper.body["data"].to_owned());
-        self.permissions = serde_json::from_value(wrapper.body["permissions"].to_owned())
-            .unwrap();
+        self.data = Some(wrapper.data());
+        self.permissions = unwrap_permissions(&wrapper);
         self.id = Some(wrapper.body["data"]["id"].as_str().unwrap().to_owned());
     }
 
@@ -97,7 +200,7 @@ impl Resource for Record {
     fn get_timestamp(&self) -> Option<u64> {
         match self.get_data() {
             Some(data) => {
-                match data["lat_modified"].as_u64() {
+                match data["last_modified"].as_u64() {
                     Some(ts) => ts.into(),
                     None => None,
                 }
@@ -170,6 +273,144 @@ mod test_record {
         assert!(create_data["last_modified"] != update_data["last_modified"]);
     }
 
+    #[test]
+    fn test_patch_record() {
+        let mut record = setup_record();
+        record.data = json!({"title": "entrecote", "price": 20}).into();
+        record.create().unwrap();
+
+        record.patch(json!({"price": 25, "title": null})).unwrap();
+        let data = record.data.unwrap();
+
+        assert_eq!(data["price"], 25);
+        assert!(data.get("title").is_none());
+    }
+
+    #[test]
+    fn test_json_patch_record() {
+        let mut record = setup_record();
+        record.data = json!({"title": "entrecote"}).into();
+        record.create().unwrap();
+
+        let operations = vec![json!({"op": "add", "path": "/data/price", "value": 25}),
+                              json!({"op": "remove", "path": "/data/title"})];
+        record.json_patch(operations).unwrap();
+        let data = record.data.unwrap();
+
+        assert_eq!(data["price"], 25);
+        assert!(data.get("title").is_none());
+    }
+
+    #[test]
+    fn test_grant_record_permissions() {
+        let mut record = setup_record();
+        record.data = json!({"title": "entrecote"}).into();
+        record.create().unwrap();
+
+        record.grant("read", "system.Everyone").unwrap();
+        assert_eq!(record.permissions.read.clone().unwrap(), vec!["system.Everyone"]);
+        assert_eq!(record.data.clone().unwrap()["title"], "entrecote");
+
+        record.revoke("read", "system.Everyone").unwrap();
+        assert_eq!(record.permissions.read, None);
+    }
+
+    #[test]
+    fn test_upload_and_download_attachment() {
+        let mut record = setup_record();
+        let attachment = record
+            .upload_attachment_from(&b"hello"[..], "hello.txt", "text/plain")
+            .unwrap();
+
+        assert_eq!(attachment.size, 5);
+        assert_eq!(record.attachment().unwrap().hash, attachment.hash);
+        assert_eq!(record.download_attachment().unwrap(), b"hello");
+
+        record.delete_attachment().unwrap();
+        assert_eq!(record.attachment(), None);
+        record.download_attachment().unwrap_err();
+    }
+
+    #[test]
+    fn test_record_history() {
+        let mut record = setup_record();
+        record.data = json!({"price": 20}).into();
+        record.create().unwrap();
+        record.patch(json!({"price": 25})).unwrap();
+        record.collection.new_record().create().unwrap();
+
+        let entries: Vec<_> = record.history().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].action, "update");
+        assert_eq!(entries[0].target["data"]["price"], 25);
+        assert_eq!(entries[1].action, "create");
+    }
+
+    #[test]
+    fn test_refresh_record() {
+        let mut record = setup_record();
+        record.create().unwrap();
+        assert!(!record.refresh().unwrap());
+
+        let mut other = record.collection.record("entrecote");
+        other.data = json!({"price": 25}).into();
+        other.set().unwrap();
+
+        assert!(record.refresh().unwrap());
+        assert_eq!(record.data.unwrap()["price"], 25);
+    }
+
+    #[test]
+    fn test_save_record_if_unchanged() {
+        let mut record = setup_record();
+        record.data = json!({"price": 20}).into();
+        record.save_if_unchanged().unwrap();
+
+        // Another writer changes the record meanwhile
+        let mut other = record.collection.record("entrecote");
+        other.load().unwrap();
+        other.data.as_mut().unwrap()["price"] = 25.into();
+        other.save_if_unchanged().unwrap();
+
+        record.data.as_mut().unwrap()["price"] = 30.into();
+        let err = record.save_if_unchanged().unwrap_err();
+        assert_eq!(err.existing().unwrap()["price"], 25);
+    }
+
+    #[test]
+    fn test_save_record_if_unchanged_fails_on_existing() {
+        let mut record = setup_record();
+        record.create().unwrap();
+
+        let mut other = record.collection.record("entrecote");
+        other.save_if_unchanged().unwrap_err();
+    }
+
+    #[test]
+    fn test_delete_record_if_unchanged() {
+        let mut record = setup_record();
+        record.create().unwrap();
+
+        let mut other = record.collection.record("entrecote");
+        other.load().unwrap();
+        other.update().unwrap();
+
+        record.delete_if_unchanged().unwrap_err();
+        other.delete_if_unchanged().unwrap();
+    }
+
+    #[test]
+    fn test_delete_record_if_unchanged_fails_without_timestamp() {
+        let mut record = setup_record();
+        record.delete_if_unchanged().unwrap_err();
+    }
+
+    #[test]
+    fn test_patch_record_fails_on_not_existing() {
+        let mut record = setup_record();
+        record.patch(json!({"price": 25})).unwrap_err();
+    }
+
     #[test]
     fn test_update_record_fails_on_not_existing() {
         let client = setup_collection();
@@ -1,15 +1,23 @@
-use std::str;
 use std::io::Read;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::Serialize;
 use serde_json;
 use serde_json::Value;
+use url::form_urlencoded::byte_serialize;
 use hyper::method::Method;
 use hyper::header::{Headers, ContentType, IfMatch, IfNoneMatch};
-use hyper::status::StatusCode;
 
 use KintoConfig;
 use error::KintoError;
 use response::ResponseWrapper;
+use retry::{RetryState, RetryStep};
+use cache::send_cached;
+#[cfg(feature = "async")]
+use async_client::{KintoFuture, send_prepared_async};
+#[cfg(feature = "async")]
+use cache::send_cached_async;
 
 
 /// Request builder used for setting data by specialized request methods.
@@ -21,6 +29,8 @@ pub struct RequestPreparer {
     pub headers: Headers,
     pub query: String,
     pub body: Option<Value>,
+    /// Non JSON body (e.g. multipart), sent instead of `body` if set.
+    pub raw_body: Option<Vec<u8>>,
 }
 
 
@@ -34,94 +44,138 @@ impl RequestPreparer {
             headers: Headers::new(),
             query: String::new(),
             body: None,
+            raw_body: None,
         }
     }
-}
 
+    /// Get the absolute url of the request, querystring included.
+    pub fn full_url(&self) -> String {
+        let full_path = format!("{}{}", self.config.server_url, self.path);
+        if self.query.is_empty() {
+            return full_path;
+        }
+        format!("{}?{}", full_path, self.query)
+    }
 
-/// Base trait with options shared with all kinto requests
-pub trait KintoRequest: Clone {
-    fn preparer(&mut self) -> &mut RequestPreparer;
+    /// Get the request headers, authentication included.
+    pub fn full_headers(&self) -> Headers {
+        let mut headers = self.headers.to_owned();
 
-    /// Set If-Match header.
-    fn if_match(&mut self, if_match: IfMatch) -> &mut Self {
-        self.preparer().headers.set(if_match);
-        self
+        // Set authentication headers
+        if let Some(ref auth) = self.config.auth {
+            auth.apply(&mut headers);
+        }
+        headers
     }
 
-    /// Set If-None-Match header.
-    fn if_none_match(&mut self, if_match: IfNoneMatch) -> &mut Self {
-        self.preparer().headers.set(if_match);
-        self
+    /// Refresh the expired credentials of the config, if possible.
+    pub fn refresh_auth(&self) -> bool {
+        match self.config.auth {
+            Some(ref auth) => auth.refresh(),
+            None => false,
+        }
     }
 
-    /// Send the request.
-    fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
-
-        // Borrow preparer mutable
-        let preparer = self.preparer();
-
-        let mut full_path = format!("{}{}",
-                                preparer.config.server_url,
-                                preparer.path);
-
-        if !preparer.query.is_empty() {
-            full_path = format!("{}?{}", full_path, preparer.query);
+    /// Get the serialized request body.
+    pub fn payload(&self) -> Vec<u8> {
+        if let Some(ref raw_body) = self.raw_body {
+            return raw_body.to_owned();
         }
+        match self.body {
+            Some(ref body) => serde_json::to_vec(body).unwrap(),
+            None => vec![],
+        }
+    }
+}
 
-        let mut headers = preparer.headers.to_owned();
 
-        // Set authentication headers
-        if let Some(ref method) = preparer.config.auth {
-            headers.set(method.clone());
-        }
+/// Send a prepared request.
+///
+/// Failed requests are retried according to the config `RetryPolicy`.
+pub fn send_prepared(preparer: &RequestPreparer) -> Result<ResponseWrapper, KintoError> {
+    let policy = preparer.config.retry_policy.clone();
+    let backoff = preparer.config.backoff();
+    let mut retry = RetryState::new(policy.clone(), preparer.method.to_owned());
 
-        let payload = match preparer.body.clone() {
-            Some(body) => serde_json::to_string(&body).unwrap(),
-            None => "".to_owned(),
-        };
+    loop {
+        if policy.respect_backoff {
+            backoff.wait();
+        }
 
         // Send prepared request
         let response = preparer
             .config
             .http_client()
-            .request(preparer.method.to_owned(), &full_path)
-            .headers(headers)
-            .body(payload.as_str())
+            .request(preparer.method.to_owned(), &preparer.full_url())
+            .headers(preparer.full_headers())
+            .body(preparer.payload().as_slice())
             .send();
 
         let mut response = match response {
             Ok(response) => response,
-            Err(_) => return Err(KintoError::HyperError),
+            Err(err) => {
+                match retry.after_error() {
+                    RetryStep::Retry(delay) => {
+                        thread::sleep(delay);
+                        continue;
+                    }
+                    _ => return Err(err.into()),
+                }
+            }
         };
 
-        // Handle sync errors
-        if response.status == StatusCode::NotModified {
-            return Err(KintoError::NotModified);
-        }
+        backoff.update(&response.headers);
 
-        if response.status == StatusCode::PreconditionFailed {
-            return Err(KintoError::PreconditionError);
+        let mut step = retry.after_response(response.status, &response.headers);
+        if step == RetryStep::RefreshAuth {
+            if preparer.refresh_auth() {
+                continue;
+            }
+            step = retry.after_response(response.status, &response.headers);
         }
-
-        // Raise on unexpected errors
-        if !response.status.is_success() {
-            return Err(KintoError::HyperError);
+        if let RetryStep::Retry(delay) = step {
+            thread::sleep(delay);
+            continue;
         }
 
         let mut serialized = String::new();
         try!(response.read_to_string(&mut serialized));
-        let body = serde_json::from_str(&serialized).unwrap();
-
-        let response = ResponseWrapper {
-            config: preparer.config.clone(),
-            path: preparer.path.to_owned(),
-            status: response.status,
-            headers: response.headers.to_owned(),
-            body: body,
-        };
 
-        Ok(response)
+        return ResponseWrapper::from_parts(preparer.config.clone(),
+                                           preparer.path.to_owned(),
+                                           response.status,
+                                           response.headers.to_owned(),
+                                           &serialized);
+    }
+}
+
+
+/// Base trait with options shared with all kinto requests
+pub trait KintoRequest: Clone {
+    fn preparer(&mut self) -> &mut RequestPreparer;
+
+    /// Set If-Match header.
+    fn if_match(&mut self, if_match: IfMatch) -> &mut Self {
+        self.preparer().headers.set(if_match);
+        self
+    }
+
+    /// Set If-None-Match header.
+    fn if_none_match(&mut self, if_match: IfNoneMatch) -> &mut Self {
+        self.preparer().headers.set(if_match);
+        self
+    }
+
+    /// Send the request.
+    fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
+        send_prepared(self.preparer())
+    }
+
+    /// Send the request with the asynchronous client, as `send` does with
+    /// the blocking one. Used by `AsyncKintoRequest::send_async`.
+    #[cfg(feature = "async")]
+    fn send_future(&mut self) -> KintoFuture<ResponseWrapper> {
+        send_prepared_async(self.preparer().clone())
     }
 
     fn follow_subrequests(&mut self) -> Result<ResponseWrapper, KintoError> {
@@ -131,20 +185,14 @@ pub trait KintoRequest: Clone {
         let mut current_response = base_response.clone();
 
         loop {
-            let page_header = match current_response.headers.get_raw("next-page") {
-                Some(values) => values[0].clone(),
+            let next_page_path = match try!(current_response.next_page_path()) {
+                Some(path) => path,
                 None => return Ok(base_response),
             };
 
-            // Gets nets page string
-            let next_page_url = try!(str::from_utf8(page_header.as_slice()));
-
             // Repeated request on the provided endpoint
             let mut temp_request = self.clone();
-
-            // Remove client prefix
-            temp_request.preparer().path =
-                next_page_url.replace(base_response.config.server_url.as_str(), "");
+            temp_request.preparer().path = next_page_path;
             temp_request.preparer().query = "".to_owned();
 
             current_response = try!(temp_request.send());
@@ -167,12 +215,117 @@ pub trait PayloadedEndpoint: KintoRequest {
     }
 }
 
+/// Join values into a comma separated list, as expected by `in_` and `exclude_`.
+fn join_values<V: ToString>(values: &[V]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+
 /// Implement methods used on plural endpoints (e.g. filters and pagination)
 pub trait PluralEndpoint: KintoRequest {
-    fn limit(&mut self, limit: i32) -> &mut Self {
-        self.preparer().query = format!("{}&_limit={}", self.preparer().query, limit);
+    /// Append an URL encoded parameter to the querystring.
+    fn query_param(&mut self, key: &str, value: &str) -> &mut Self {
+        {
+            let preparer = self.preparer();
+            if !preparer.query.is_empty() {
+                preparer.query.push('&');
+            }
+            preparer.query.extend(byte_serialize(key.as_bytes()));
+            preparer.query.push('=');
+            preparer.query.extend(byte_serialize(value.as_bytes()));
+        }
         self
     }
+
+    fn limit(&mut self, limit: i32) -> &mut Self {
+        self.query_param("_limit", &limit.to_string())
+    }
+
+    /// Only return objects modified after the given timestamp, tombstones included.
+    fn since(&mut self, timestamp: u64) -> &mut Self {
+        self.query_param("_since", &timestamp.to_string())
+    }
+
+    /// Only return objects modified before the given timestamp.
+    fn before(&mut self, timestamp: u64) -> &mut Self {
+        self.query_param("_before", &timestamp.to_string())
+    }
+
+    /// Sort results on the given fields. Prefix a field with `-` for descending order.
+    fn sort(&mut self, fields: &[&str]) -> &mut Self {
+        self.query_param("_sort", &fields.join(","))
+    }
+
+    /// Only return the given fields of each object.
+    fn fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.query_param("_fields", &fields.join(","))
+    }
+
+    /// Filter objects whose field is equal to the value.
+    fn eq<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(field, &value.to_string())
+    }
+
+    /// Filter objects whose field is greater or equal to the value.
+    fn min_<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(&format!("min_{}", field), &value.to_string())
+    }
+
+    /// Filter objects whose field is lower or equal to the value.
+    fn max_<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(&format!("max_{}", field), &value.to_string())
+    }
+
+    /// Filter objects whose field is strictly lower than the value.
+    fn lt_<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(&format!("lt_{}", field), &value.to_string())
+    }
+
+    /// Filter objects whose field is strictly greater than the value.
+    fn gt_<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(&format!("gt_{}", field), &value.to_string())
+    }
+
+    /// Filter objects whose field is one of the values.
+    fn in_<V: ToString>(&mut self, field: &str, values: &[V]) -> &mut Self {
+        self.query_param(&format!("in_{}", field), &join_values(values))
+    }
+
+    /// Filter objects whose field is none of the values.
+    fn exclude_<V: ToString>(&mut self, field: &str, values: &[V]) -> &mut Self {
+        self.query_param(&format!("exclude_{}", field), &join_values(values))
+    }
+
+    /// Filter objects whose field is different from the value.
+    fn not_<V: ToString>(&mut self, field: &str, value: V) -> &mut Self {
+        self.query_param(&format!("not_{}", field), &value.to_string())
+    }
+
+    /// Filter objects whose field matches the pattern (`*` is a wildcard).
+    fn like_(&mut self, field: &str, pattern: &str) -> &mut Self {
+        self.query_param(&format!("like_{}", field), pattern)
+    }
+
+    /// Filter objects on the presence (or absence) of a field.
+    fn has_(&mut self, field: &str, present: bool) -> &mut Self {
+        self.query_param(&format!("has_{}", field), &present.to_string())
+    }
+
+    /// Filter objects whose array field contains all the values.
+    fn contains_<V: Serialize>(&mut self, field: &str, values: &[V]) -> &mut Self {
+        let encoded = serde_json::to_string(values).unwrap();
+        self.query_param(&format!("contains_{}", field), &encoded)
+    }
+
+    /// Filter objects whose array field contains any of the values.
+    fn contains_any_<V: Serialize>(&mut self, field: &str, values: &[V]) -> &mut Self {
+        let encoded = serde_json::to_string(values).unwrap();
+        self.query_param(&format!("contains_any_{}", field), &encoded)
+    }
 }
 
 /// Get request on plural endpoints.
@@ -193,11 +346,55 @@ impl KintoRequest for GetCollection {
     fn preparer(&mut self) -> &mut RequestPreparer {
         &mut self.preparer
     }
+
+    fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
+        send_cached(&self.preparer)
+    }
+
+    #[cfg(feature = "async")]
+    fn send_future(&mut self) -> KintoFuture<ResponseWrapper> {
+        send_cached_async(self.preparer.clone())
+    }
 }
 
 impl PluralEndpoint for GetCollection {}
 
 
+/// Head request on plural endpoints, used to count objects.
+#[derive(Debug, Clone)]
+pub struct HeadCollection {
+    pub preparer: RequestPreparer,
+}
+
+impl HeadCollection {
+    pub fn new(config: KintoConfig, path: String) -> HeadCollection {
+        let mut preparer = RequestPreparer::new(config, path);
+        preparer.method = Method::Head;
+        HeadCollection { preparer }
+    }
+
+    /// Send the request and read the number of matching objects.
+    pub fn count(&mut self) -> Result<u64, KintoError> {
+        let response = try!(self.send());
+        match response.total_objects() {
+            Some(total) => Ok(total),
+            None => {
+                let message = "missing Total-Objects header".to_owned();
+                Err(KintoError::InvalidResponseError(message))
+            }
+        }
+    }
+}
+
+impl KintoRequest for HeadCollection {
+    fn preparer(&mut self) -> &mut RequestPreparer {
+        &mut self.preparer
+    }
+}
+
+impl PluralEndpoint for HeadCollection {}
+
+
 /// Delete request on plural endpoints.
 #[derive(Debug, Clone)]
 pub struct DeleteCollection {
@@ -243,6 +440,62 @@ impl KintoRequest for CreateRecord {
 impl PayloadedEndpoint for CreateRecord {}
 
 
+/// Multipart upload request of a record attachment (kinto-attachment plugin).
+#[derive(Debug, Clone)]
+pub struct UploadAttachment {
+    pub preparer: RequestPreparer,
+}
+
+impl UploadAttachment {
+    pub fn new(config: KintoConfig, path: String) -> UploadAttachment {
+        let mut preparer = RequestPreparer::new(config, path);
+        preparer.method = Method::Post;
+        UploadAttachment { preparer }
+    }
+
+    /// Set the uploaded file as a `multipart/form-data` body.
+    pub fn file(&mut self, filename: &str, mimetype: &str, content: &[u8]) -> &mut Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let mut boundary = format!("kinto-http-{:08x}", nanos);
+
+        // The boundary must not appear in the file content
+        while content
+                  .windows(boundary.len())
+                  .any(|window| window == boundary.as_bytes()) {
+            boundary.push('x');
+        }
+
+        let filename = filename.replace('"', "\\\"");
+        let mut body = format!("--{}\r\n\
+                                Content-Disposition: form-data; name=\"attachment\"; \
+                                filename=\"{}\"\r\n\
+                                Content-Type: {}\r\n\r\n",
+                               boundary,
+                               filename,
+                               mimetype)
+                .into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        self.preparer
+            .headers
+            .set_raw("Content-Type", vec![content_type.into_bytes()]);
+        self.preparer.raw_body = Some(body);
+        self
+    }
+}
+
+impl KintoRequest for UploadAttachment {
+    fn preparer(&mut self) -> &mut RequestPreparer {
+        &mut self.preparer
+    }
+}
+
+
 /// Get request on single endpoints.
 #[derive(Debug, Clone)]
 pub struct GetRecord {
@@ -261,6 +514,15 @@ impl KintoRequest for GetRecord {
     fn preparer(&mut self) -> &mut RequestPreparer {
         &mut self.preparer
     }
+
+    fn send(&mut self) -> Result<ResponseWrapper, KintoError> {
+        send_cached(&self.preparer)
+    }
+
+    #[cfg(feature = "async")]
+    fn send_future(&mut self) -> KintoFuture<ResponseWrapper> {
+        send_cached_async(self.preparer.clone())
+    }
 }
 
 /// Update request on single endpoints.
@@ -300,6 +562,26 @@ impl PatchRecord {
     }
 }
 
+impl PatchRecord {
+    /// Set a JSON Merge Patch (RFC 7396) body.
+    pub fn merge_patch(&mut self, body: Value) -> &mut Self {
+        self.preparer
+            .headers
+            .set_raw("Content-Type", vec![b"application/merge-patch+json".to_vec()]);
+        self.preparer.body = body.into();
+        self
+    }
+
+    /// Set a JSON Patch (RFC 6902) body from a list of operations.
+    pub fn json_patch(&mut self, operations: Vec<Value>) -> &mut Self {
+        self.preparer
+            .headers
+            .set_raw("Content-Type", vec![b"application/json-patch+json".to_vec()]);
+        self.preparer.body = Value::Array(operations).into();
+        self
+    }
+}
+
 impl KintoRequest for PatchRecord {
     fn preparer(&mut self) -> &mut RequestPreparer {
         &mut self.preparer
@@ -328,3 +610,156 @@ impl KintoRequest for DeleteRecord {
         &mut self.preparer
     }
 }
+
+
+#[cfg(test)]
+mod test_plural_endpoint {
+    use hyper::method::Method;
+
+    use request::{GetCollection, HeadCollection, DeleteCollection, PluralEndpoint};
+    use utils::tests::setup_config;
+
+    fn get_request() -> GetCollection {
+        GetCollection::new(setup_config(), "/buckets/food/collections/meat/records".to_owned())
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut request = get_request();
+        request.limit(3);
+        assert_eq!(request.preparer.query, "_limit=3");
+    }
+
+    #[test]
+    fn test_head_request_filters() {
+        let path = "/buckets/food/collections".to_owned();
+        let mut request = HeadCollection::new(setup_config(), path);
+        request.eq("kind", "meat");
+        assert_eq!(request.preparer.method, Method::Head);
+        assert_eq!(request.preparer.query, "kind=meat");
+    }
+
+    #[test]
+    fn test_filters_are_joined() {
+        let mut request = get_request();
+        request.eq("name", "steak").min_("price", 10).max_("price", 20);
+        assert_eq!(request.preparer.query, "name=steak&min_price=10&max_price=20");
+    }
+
+    #[test]
+    fn test_comparison_filters() {
+        let mut request = get_request();
+        request.lt_("age", 3).gt_("age", 1).not_("kind", "fish");
+        assert_eq!(request.preparer.query, "lt_age=3&gt_age=1&not_kind=fish");
+    }
+
+    #[test]
+    fn test_list_filters() {
+        let mut request = get_request();
+        request.in_("id", &["a", "b"]).exclude_("size", &[1, 2]);
+        assert_eq!(request.preparer.query, "in_id=a%2Cb&exclude_size=1%2C2");
+    }
+
+    #[test]
+    fn test_like_and_has_filters() {
+        let mut request = get_request();
+        request.like_("title", "*good meat*").has_("deleted", false);
+        assert_eq!(request.preparer.query,
+                   "like_title=*good+meat*&has_deleted=false");
+    }
+
+    #[test]
+    fn test_contains_filters() {
+        let mut request = get_request();
+        request.contains_("tags", &["a"]).contains_any_("ids", &[1, 2]);
+        assert_eq!(request.preparer.query,
+                   "contains_tags=%5B%22a%22%5D&contains_any_ids=%5B1%2C2%5D");
+    }
+
+    #[test]
+    fn test_since_and_before() {
+        let mut request = get_request();
+        request.since(1234).before(5678);
+        assert_eq!(request.preparer.query, "_since=1234&_before=5678");
+    }
+
+    #[test]
+    fn test_sort_and_fields() {
+        let mut request = get_request();
+        request.sort(&["-last_modified", "title"]).fields(&["title", "price"]);
+        assert_eq!(request.preparer.query,
+                   "_sort=-last_modified%2Ctitle&_fields=title%2Cprice");
+    }
+
+    #[test]
+    fn test_field_names_are_encoded() {
+        let mut request = get_request();
+        request.eq("a&b", "c=d");
+        assert_eq!(request.preparer.query, "a%26b=c%3Dd");
+    }
+
+    #[test]
+    fn test_delete_collection_filters() {
+        let mut request = DeleteCollection::new(setup_config(), "/buckets".to_owned());
+        request.eq("kind", "meat").limit(5);
+        assert_eq!(request.preparer.query, "kind=meat&_limit=5");
+    }
+}
+
+
+#[cfg(test)]
+mod test_patch_record {
+    use serde_json::Value;
+
+    use request::PatchRecord;
+    use utils::tests::setup_config;
+
+    fn content_type(request: &PatchRecord) -> Vec<u8> {
+        request.preparer.headers.get_raw("Content-Type").unwrap()[0].clone()
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut request = PatchRecord::new(setup_config(), "/buckets/food".to_owned());
+        request.merge_patch(json!({"data": {"title": null}}));
+        assert_eq!(content_type(&request), b"application/merge-patch+json".to_vec());
+        assert_eq!(request.preparer.body, Some(json!({"data": {"title": null}})));
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let mut request = PatchRecord::new(setup_config(), "/buckets/food".to_owned());
+        let operations = vec![json!({"op": "add", "path": "/data/title", "value": "a"})];
+        request.json_patch(operations.clone());
+        assert_eq!(content_type(&request), b"application/json-patch+json".to_vec());
+        assert_eq!(request.preparer.body, Some(Value::Array(operations)));
+    }
+}
+
+
+#[cfg(test)]
+mod test_upload_attachment {
+    use std::str;
+
+    use request::UploadAttachment;
+    use utils::tests::setup_config;
+
+    #[test]
+    fn test_multipart_body() {
+        let path = "/buckets/food/collections/meat/records/a/attachment".to_owned();
+        let mut request = UploadAttachment::new(setup_config(), path);
+        request.file("hello.txt", "text/plain", b"hello");
+
+        let raw = request.preparer.headers.get_raw("Content-Type").unwrap();
+        let content_type = String::from_utf8(raw[0].clone()).unwrap();
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+        let boundary = &content_type["multipart/form-data; boundary=".len()..];
+
+        let payload = request.preparer.payload();
+        let body = str::from_utf8(&payload).unwrap();
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        assert!(body.contains("name=\"attachment\"; filename=\"hello.txt\"\r\n"));
+        assert!(body.contains("Content-Type: text/plain\r\n\r\nhello\r\n"));
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+}
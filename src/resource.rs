@@ -3,10 +3,11 @@ use hyper::header::{IfMatch, IfNoneMatch};
 
 use client::KintoConfig;
 use error::KintoError;
-use request::{GetRecord, CreateRecord, UpdateRecord, DeleteRecord, GetCollection,
-              DeleteCollection, KintoRequest, PayloadedEndpoint};
+use request::{GetRecord, CreateRecord, UpdateRecord, PatchRecord, DeleteRecord,
+              GetCollection, HeadCollection, DeleteCollection, KintoRequest,
+              PayloadedEndpoint};
 use response::ResponseWrapper;
-use utils::timestamp_to_etag;
+use utils::{timestamp_to_etag, permission_pointer};
 
 
 /// Implement a Kinto core object endpoint.
@@ -92,6 +93,11 @@ pub trait Resource: Clone {
         Ok(UpdateRecord::new(self.get_config(), try!(self.record_path())))
     }
 
+    /// Create a custom patch (PATCH) request for the endpoint.
+    fn patch_request(&self) -> Result<PatchRecord, KintoError> {
+        Ok(PatchRecord::new(self.get_config(), try!(self.record_path())))
+    }
+
     /// Create a custom delete request for the endpoint.
     fn delete_request(&self) -> Result<DeleteRecord, KintoError> {
         Ok(DeleteRecord::new(self.get_config(), try!(self.record_path())))
@@ -102,6 +108,11 @@ pub trait Resource: Clone {
         Ok(GetCollection::new(self.get_config(), try!(self.resource_path())))
     }
 
+    /// Create a custom count (HEAD) collections request.
+    fn count_request(&self) -> Result<HeadCollection, KintoError> {
+        Ok(HeadCollection::new(self.get_config(), try!(self.resource_path())))
+    }
+
     /// Create a custom delete collections request.
     fn delete_all_request(&self) -> Result<DeleteCollection, KintoError> {
         Ok(DeleteCollection::new(self.get_config(), try!(self.resource_path())))
@@ -117,6 +128,26 @@ pub trait Resource: Clone {
         Ok(())
     }
 
+    /// Reload the object only if it changed on the server since its
+    /// timestamp.
+    ///
+    /// Return `false` if the object is unchanged (`304 Not Modified`).
+    fn refresh(&mut self) -> Result<bool, KintoError> {
+        let mut request = try!(self.load_request());
+        if let Some(stamp) = self.get_timestamp() {
+            request.if_none_match(IfNoneMatch::Items(timestamp_to_etag(stamp)));
+        }
+
+        match request.send() {
+            Ok(wrapper) => {
+                self.unwrap_response(wrapper);
+                Ok(true)
+            }
+            Err(KintoError::NotModified) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
     /// Set current object to the server (create or update).
     fn set(&mut self) -> Result<(), KintoError> {
         if self.get_id() == None {
@@ -167,6 +198,99 @@ pub trait Resource: Clone {
         Ok(())
     }
 
+    /// Save the object only if it was not changed on the server since it
+    /// was loaded, or only if it does not exist yet when it has no timestamp.
+    ///
+    /// Fails with `KintoError::Conflict`, carrying the current server
+    /// version, otherwise.
+    fn save_if_unchanged(&mut self) -> Result<(), KintoError> {
+        let stamp = match self.get_timestamp() {
+            Some(stamp) => stamp,
+            None => return self.create(),
+        };
+
+        let wrapper = try!(try!(self.update_request())
+                               .body(self.get_body().into())
+                               .if_match(IfMatch::Items(timestamp_to_etag(stamp)))
+                               .send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
+    /// Delete the object only if it was not changed on the server since it
+    /// was loaded.
+    ///
+    /// Fails with `KintoError::Conflict` otherwise.
+    fn delete_if_unchanged(&mut self) -> Result<(), KintoError> {
+        let stamp = match self.get_timestamp() {
+            Some(stamp) => stamp,
+            None => return Err(KintoError::UndefinedTimestampError),
+        };
+
+        let wrapper = try!(try!(self.delete_request())
+                               .if_match(IfMatch::Items(timestamp_to_etag(stamp)))
+                               .send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
+    /// Apply a JSON Merge Patch on the object data, leaving other fields as is.
+    ///
+    /// Fails with `KintoError::Conflict` if the object was modified
+    /// since its timestamp.
+    fn patch(&mut self, changes: Value) -> Result<(), KintoError> {
+        let mut request = try!(self.patch_request());
+        request.merge_patch(json!({"data": changes}));
+        if let Some(stamp) = self.get_timestamp() {
+            request.if_match(IfMatch::Items(timestamp_to_etag(stamp)));
+        }
+
+        let wrapper = try!(request.send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
+    /// Apply JSON Patch operations (e.g. on `/data/field` or
+    /// `/permissions/read/principal`) on the object.
+    fn json_patch(&mut self, operations: Vec<Value>) -> Result<(), KintoError> {
+        let mut request = try!(self.patch_request());
+        request.json_patch(operations);
+        if let Some(stamp) = self.get_timestamp() {
+            request.if_match(IfMatch::Items(timestamp_to_etag(stamp)));
+        }
+
+        let wrapper = try!(request.send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
+    /// Add a principal to a permission (e.g. "read"), leaving other
+    /// principals and the data untouched.
+    fn grant(&mut self, permission: &str, principal: &str) -> Result<(), KintoError> {
+        let operation = json!({
+            "op": "add",
+            "path": permission_pointer(permission, principal),
+        });
+
+        // No If-Match: the server applies the operation atomically.
+        let wrapper = try!(try!(self.patch_request()).json_patch(vec![operation]).send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
+    /// Remove a principal from a permission, leaving other principals and
+    /// the data untouched.
+    fn revoke(&mut self, permission: &str, principal: &str) -> Result<(), KintoError> {
+        let operation = json!({
+            "op": "remove",
+            "path": permission_pointer(permission, principal),
+        });
+
+        let wrapper = try!(try!(self.patch_request()).json_patch(vec![operation]).send());
+        self.unwrap_response(wrapper);
+        Ok(())
+    }
+
     /// Delete the current object from the server if exists.
     fn delete(&mut self) -> Result<(), KintoError> {
         let wrapper = match self.delete_request().unwrap().send() {
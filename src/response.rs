@@ -1,8 +1,12 @@
+use std::str;
+
 use hyper::status::StatusCode;
-use hyper::header::Headers;
+use hyper::header::{Headers, ETag};
+use serde_json;
 use serde_json::Value;
 
 use KintoConfig;
+use error::KintoError;
 
 
 /// Wrapper for a Kinto response object.
@@ -14,3 +18,88 @@ pub struct ResponseWrapper {
     pub headers: Headers,
     pub body: Value,
 }
+
+
+impl ResponseWrapper {
+    /// Build a response from the raw server response, raising on errors.
+    pub fn from_parts(config: KintoConfig,
+                      path: String,
+                      status: StatusCode,
+                      headers: Headers,
+                      body: &str)
+                      -> Result<ResponseWrapper, KintoError> {
+        // Handle sync errors
+        if status == StatusCode::NotModified {
+            return Err(KintoError::NotModified);
+        }
+
+        // Raise on unexpected errors with the server error details
+        if !status.is_success() {
+            return Err(KintoError::from_response(status, body));
+        }
+
+        Ok(ResponseWrapper {
+               config,
+               path,
+               status,
+               headers,
+               // HEAD and some deletion responses have no body
+               body: if body.is_empty() {
+                   Value::Null
+               } else {
+                   try!(serde_json::from_str(body))
+               },
+           })
+    }
+
+    /// Get the response data, with `last_modified` taken from the `ETag`
+    /// header when the body does not contain it.
+    pub fn data(&self) -> Value {
+        let mut data = self.body["data"].to_owned();
+        if data.is_object() && data["last_modified"].is_null() {
+            if let Some(timestamp) = self.etag_timestamp() {
+                data["last_modified"] = timestamp.into();
+            }
+        }
+        data
+    }
+
+    /// Get the timestamp from the response `ETag` header.
+    pub fn etag_timestamp(&self) -> Option<u64> {
+        match self.headers.get::<ETag>() {
+            Some(etag) => etag.tag().trim_matches('"').parse().ok(),
+            None => None,
+        }
+    }
+
+    /// Get the number of objects matching a plural request, from the
+    /// `Total-Objects` header (`Total-Records` on older servers).
+    pub fn total_objects(&self) -> Option<u64> {
+        let raw = match self.headers.get_raw("Total-Objects") {
+            Some(values) => values[0].clone(),
+            None => {
+                match self.headers.get_raw("Total-Records") {
+                    Some(values) => values[0].clone(),
+                    None => return None,
+                }
+            }
+        };
+        match str::from_utf8(raw.as_slice()) {
+            Ok(value) => value.trim().parse().ok(),
+            Err(_) => None,
+        }
+    }
+
+    /// Get the path of the next page from the `Next-Page` header, if any.
+    ///
+    /// The server url prefix is removed so that it can be used as a request
+    /// path; the querystring (including the pagination token) is kept.
+    pub fn next_page_path(&self) -> Result<Option<String>, KintoError> {
+        let page_header = match self.headers.get_raw("next-page") {
+            Some(values) => values[0].clone(),
+            None => return Ok(None),
+        };
+        let next_page_url = try!(str::from_utf8(page_header.as_slice()));
+        Ok(Some(next_page_url.replace(self.config.server_url.as_str(), "")))
+    }
+}
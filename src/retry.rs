@@ -0,0 +1,325 @@
+use std::cmp;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::method::Method;
+use hyper::header::Headers;
+use hyper::status::StatusCode;
+
+
+/// Policy used to retry failed requests.
+///
+/// The default policy does not retry nor wait for the server backoff, which
+/// is the historical behaviour of the client.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, the first one included.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on each following attempt.
+    pub base_delay: Duration,
+    /// Upper bound of the computed delays.
+    pub max_delay: Duration,
+    /// Randomize delays between half and the full computed value.
+    pub jitter: bool,
+    /// Methods which can be safely sent again.
+    pub idempotent_methods: Vec<Method>,
+    /// Response statuses which trigger a retry.
+    pub retry_statuses: Vec<StatusCode>,
+    /// Wait for the delay requested by the server `Backoff` header before
+    /// sending requests.
+    pub respect_backoff: bool,
+}
+
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            idempotent_methods: vec![Method::Get,
+                                     Method::Head,
+                                     Method::Options,
+                                     Method::Put,
+                                     Method::Delete],
+            retry_statuses: vec![StatusCode::TooManyRequests,
+                                 StatusCode::BadGateway,
+                                 StatusCode::ServiceUnavailable,
+                                 StatusCode::GatewayTimeout],
+            respect_backoff: false,
+        }
+    }
+}
+
+
+impl RetryPolicy {
+    /// Policy retrying idempotent requests and honoring the server backoff.
+    pub fn with_retries(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            respect_backoff: true,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Whether a request with this method can be retried after `attempt`.
+    pub fn can_retry(&self, method: &Method, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.idempotent_methods.contains(method)
+    }
+
+    /// Whether the response status should be retried.
+    pub fn should_retry_status(&self, status: StatusCode) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// Delay to wait before sending the given attempt again.
+    ///
+    /// A delay requested by the server (`Retry-After`) always wins over the
+    /// computed exponential delay.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(delay) = retry_after {
+            return delay;
+        }
+
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = cmp::min(self.base_delay * factor, self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+
+        // Cheap jitter from the clock, between 50% and 100% of the delay.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        delay / 2 + delay / 2 * (nanos % 1000) / 1000
+    }
+}
+
+
+/// Next step after an attempt to send a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryStep {
+    /// Return the response, or the error.
+    Done,
+    /// Refresh the credentials, and send the request again if they changed.
+    RefreshAuth,
+    /// Wait for the delay, then send the request again.
+    Retry(Duration),
+}
+
+
+/// Attempts of a request, shared by the blocking and asynchronous clients.
+#[derive(Debug, Clone)]
+pub struct RetryState {
+    policy: RetryPolicy,
+    method: Method,
+    attempt: u32,
+    refreshed: bool,
+}
+
+
+impl RetryState {
+    pub fn new(policy: RetryPolicy, method: Method) -> RetryState {
+        RetryState {
+            policy,
+            method,
+            attempt: 1,
+            refreshed: false,
+        }
+    }
+
+    /// Decide what to do after a network error.
+    ///
+    /// Network errors are retried on idempotent requests only.
+    pub fn after_error(&mut self) -> RetryStep {
+        if !self.policy.can_retry(&self.method, self.attempt) {
+            return RetryStep::Done;
+        }
+        let delay = self.policy.delay(self.attempt, None);
+        self.attempt += 1;
+        RetryStep::Retry(delay)
+    }
+
+    /// Decide what to do after a response.
+    ///
+    /// Credentials are refreshed once if they expired. If they could not be,
+    /// call it again to check whether the status can be retried.
+    pub fn after_response(&mut self, status: StatusCode, headers: &Headers) -> RetryStep {
+        if status == StatusCode::Unauthorized && !self.refreshed {
+            self.refreshed = true;
+            return RetryStep::RefreshAuth;
+        }
+        if self.policy.should_retry_status(status) &&
+           self.policy.can_retry(&self.method, self.attempt) {
+            let retry_after = header_seconds(headers, "Retry-After");
+            let delay = self.policy.delay(self.attempt, retry_after);
+            self.attempt += 1;
+            return RetryStep::Retry(delay);
+        }
+        RetryStep::Done
+    }
+}
+
+
+/// Read a header value expressed in seconds.
+pub fn header_seconds(headers: &Headers, name: &str) -> Option<Duration> {
+    let raw = match headers.get_raw(name) {
+        Some(values) => values[0].clone(),
+        None => return None,
+    };
+    match str::from_utf8(raw.as_slice()) {
+        Ok(value) => value.trim().parse().ok().map(Duration::from_secs),
+        Err(_) => None,
+    }
+}
+
+
+/// Backoff requested by the server, shared by every clone of a config.
+#[derive(Debug, Clone, Default)]
+pub struct BackoffState {
+    until: Arc<Mutex<Option<Instant>>>,
+}
+
+
+impl BackoffState {
+    /// Record the `Backoff` header of a response, if any.
+    pub fn update(&self, headers: &Headers) {
+        if let Some(delay) = header_seconds(headers, "Backoff") {
+            let mut until = self.until.lock().unwrap();
+            let deadline = Instant::now() + delay;
+            *until = match *until {
+                Some(current) if current > deadline => Some(current),
+                _ => Some(deadline),
+            };
+        }
+    }
+
+    /// Remaining time before the server accepts requests again, if any.
+    pub fn remaining(&self) -> Option<Duration> {
+        let until = self.until.lock().unwrap();
+        match *until {
+            Some(deadline) => {
+                let now = Instant::now();
+                if deadline > now {
+                    Some(deadline - now)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        }
+    }
+
+    /// Whether the server currently asks clients to back off.
+    pub fn is_active(&self) -> bool {
+        self.remaining().is_some()
+    }
+
+    /// Block the current thread until the backoff period is over.
+    pub fn wait(&self) {
+        if let Some(delay) = self.remaining() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_retry {
+    use std::time::Duration;
+
+    use hyper::method::Method;
+    use hyper::header::Headers;
+    use hyper::status::StatusCode;
+
+    use retry::{RetryPolicy, RetryState, RetryStep, BackoffState, header_seconds};
+
+    #[test]
+    fn test_default_policy_does_not_retry() {
+        let policy = RetryPolicy::default();
+        assert!(!policy.can_retry(&Method::Get, 1));
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_are_retried() {
+        let policy = RetryPolicy::with_retries(3);
+        assert!(policy.can_retry(&Method::Get, 1));
+        assert!(policy.can_retry(&Method::Delete, 2));
+        assert!(!policy.can_retry(&Method::Delete, 3));
+        assert!(!policy.can_retry(&Method::Post, 1));
+        assert!(policy.should_retry_status(StatusCode::ServiceUnavailable));
+        assert!(!policy.should_retry_status(StatusCode::BadRequest));
+    }
+
+    #[test]
+    fn test_exponential_delay() {
+        let mut policy = RetryPolicy::with_retries(5);
+        policy.jitter = false;
+        assert_eq!(policy.delay(1, None), Duration::from_millis(500));
+        assert_eq!(policy.delay(3, None), Duration::from_millis(2000));
+        assert_eq!(policy.delay(20, None), Duration::from_secs(30));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(7))),
+                   Duration::from_secs(7));
+    }
+
+    #[test]
+    fn test_jittered_delay() {
+        let policy = RetryPolicy::with_retries(5);
+        let delay = policy.delay(2, None);
+        assert!(delay >= Duration::from_millis(500));
+        assert!(delay <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_retry_state() {
+        let mut policy = RetryPolicy::with_retries(2);
+        policy.jitter = false;
+        let mut headers = Headers::new();
+
+        let mut state = RetryState::new(policy.clone(), Method::Get);
+        assert_eq!(state.after_response(StatusCode::Unauthorized, &headers),
+                   RetryStep::RefreshAuth);
+        assert_eq!(state.after_response(StatusCode::Unauthorized, &headers),
+                   RetryStep::Done);
+        headers.set_raw("Retry-After", vec![b"3".to_vec()]);
+        assert_eq!(state.after_response(StatusCode::ServiceUnavailable, &headers),
+                   RetryStep::Retry(Duration::from_secs(3)));
+        assert_eq!(state.after_response(StatusCode::ServiceUnavailable, &headers),
+                   RetryStep::Done);
+
+        let mut state = RetryState::new(policy.clone(), Method::Put);
+        assert_eq!(state.after_error(), RetryStep::Retry(Duration::from_millis(500)));
+        assert_eq!(state.after_error(), RetryStep::Done);
+
+        let mut state = RetryState::new(policy, Method::Post);
+        assert_eq!(state.after_error(), RetryStep::Done);
+        assert_eq!(state.after_response(StatusCode::ServiceUnavailable, &headers),
+                   RetryStep::Done);
+    }
+
+    #[test]
+    fn test_backoff_state() {
+        let state = BackoffState::default();
+        assert!(!state.is_active());
+
+        let mut headers = Headers::new();
+        headers.set_raw("Backoff", vec![b"60".to_vec()]);
+        state.update(&headers);
+
+        assert!(state.clone().is_active());
+        assert!(state.remaining().unwrap() > Duration::from_secs(50));
+    }
+
+    #[test]
+    fn test_header_seconds() {
+        let mut headers = Headers::new();
+        headers.set_raw("Retry-After", vec![b" 30 ".to_vec()]);
+        assert_eq!(header_seconds(&headers, "Retry-After"),
+                   Some(Duration::from_secs(30)));
+        assert_eq!(header_seconds(&headers, "Backoff"), None);
+    }
+}
@@ -0,0 +1,113 @@
+use serde_json;
+use serde_json::Value;
+use serde_json::map::Map;
+
+use KintoConfig;
+use error::KintoError;
+use paths::Paths;
+use request::{KintoRequest, GetRecord};
+
+
+/// Public settings exposed by the server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerSettings {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub batch_max_requests: Option<usize>,
+    #[serde(default)]
+    pub readonly: bool,
+    /// Other settings, depending on the server configuration.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+
+/// User authenticated by the server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerUser {
+    pub id: String,
+    #[serde(default)]
+    pub principals: Vec<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub bucket: Option<String>,
+}
+
+
+/// Information returned by the server root endpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerInfo {
+    #[serde(default)]
+    pub project_name: String,
+    #[serde(default)]
+    pub project_version: String,
+    #[serde(default)]
+    pub project_docs: String,
+    #[serde(default)]
+    pub http_api_version: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub settings: ServerSettings,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub user: Option<ServerUser>,
+    #[serde(default)]
+    pub capabilities: Map<String, Value>,
+}
+
+
+impl ServerInfo {
+    /// Get the information from the server root endpoint.
+    pub fn fetch(config: KintoConfig) -> Result<ServerInfo, KintoError> {
+        let mut request = GetRecord::new(config, Paths::Root.into());
+        let response = try!(request.send());
+        Ok(try!(serde_json::from_value(response.body)))
+    }
+
+    /// Whether the server exposes the given capability (e.g. "history").
+    pub fn has_capability(&self, name: &str) -> bool {
+        self.capabilities.contains_key(name)
+    }
+
+    /// Get the details of a capability.
+    pub fn capability(&self, name: &str) -> Option<&Value> {
+        self.capabilities.get(name)
+    }
+}
+
+
+#[cfg(test)]
+mod test_server_info {
+    use serde_json;
+
+    use server_info::ServerInfo;
+
+    #[test]
+    fn test_parse_server_info() {
+        let body = json!({
+            "project_name": "kinto",
+            "project_version": "6.1.0",
+            "http_api_version": "1.15",
+            "url": "http://localhost:8888/v1/",
+            "settings": {"batch_max_requests": 25, "readonly": false, "eos": null},
+            "user": {"id": "basicauth:abc", "principals": ["system.Everyone"]},
+            "capabilities": {"flush": {"description": "Flush endpoint"}},
+        });
+        let info: ServerInfo = serde_json::from_value(body).unwrap();
+
+        assert_eq!(info.http_api_version, "1.15");
+        assert_eq!(info.settings.batch_max_requests, Some(25));
+        assert!(info.settings.extra.contains_key("eos"));
+        assert_eq!(info.user.as_ref().unwrap().id, "basicauth:abc");
+        assert!(info.has_capability("flush"));
+        assert!(!info.has_capability("history"));
+        assert_eq!(info.capability("flush").unwrap()["description"], "Flush endpoint");
+    }
+
+    #[test]
+    fn test_parse_anonymous_server_info() {
+        let body = json!({"project_name": "kinto", "settings": {"readonly": true}});
+        let info: ServerInfo = serde_json::from_value(body).unwrap();
+        assert!(info.user.is_none());
+        assert!(info.settings.readonly);
+        assert_eq!(info.settings.batch_max_requests, None);
+    }
+}
@@ -0,0 +1,487 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json;
+use serde_json::Value;
+use openssl::base64;
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::stack::Stack;
+use openssl::x509::{X509, X509Ref, X509StoreContext};
+use openssl::x509::store::X509StoreBuilder;
+use openssl_sys as ffi;
+use foreign_types::ForeignTypeRef;
+
+use KintoConfig;
+use error::KintoError;
+
+
+/// Prefix of the data signed for Content-Signature.
+const SIGNATURE_PREFIX: &[u8] = b"Content-Signature:\x00";
+
+/// Length of a P-384 `r || s` signature.
+const P384_SIGNATURE_LENGTH: usize = 96;
+
+
+/// Signature metadata set by kinto-signer on a signed collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignatureMetadata {
+    /// Base64url encoded `r || s` ECDSA signature.
+    pub signature: String,
+    /// Url of the PEM certificate chain, leaf first.
+    pub x5u: String,
+    #[serde(default)]
+    pub mode: String,
+}
+
+
+impl SignatureMetadata {
+    /// Read the signature from the collection data, if any.
+    pub fn from_data(data: &Value) -> Option<SignatureMetadata> {
+        if !data["signature"].is_object() {
+            return None;
+        }
+        serde_json::from_value(data["signature"].to_owned()).ok()
+    }
+}
+
+
+fn signature_error(message: &str) -> KintoError {
+    KintoError::SignatureError(message.to_owned())
+}
+
+
+/// Verifier of Content-Signatures (`p384ecdsa` mode), trusting code signing
+/// certificates issued for a given name under a configurable root.
+#[derive(Debug, Clone)]
+pub struct SignatureVerifier {
+    trusted_root: X509,
+    subject: String,
+    chain: Option<Vec<X509>>,
+}
+
+
+impl SignatureVerifier {
+    /// Trust leaf certificates issued for the subject name (e.g.
+    /// "remote-settings.content-signature.mozilla.org") under the given PEM
+    /// root certificate.
+    pub fn new(trusted_root_pem: &[u8],
+               subject: &str)
+               -> Result<SignatureVerifier, KintoError> {
+        Ok(SignatureVerifier {
+               trusted_root: try!(X509::from_pem(trusted_root_pem)),
+               subject: subject.to_owned(),
+               chain: None,
+           })
+    }
+
+    /// Trust leaf certificates issued for the subject name under the PEM root
+    /// certificate of a file.
+    pub fn from_root_file<P>(path: P,
+                             subject: &str)
+                             -> Result<SignatureVerifier, KintoError>
+        where P: AsRef<Path>
+    {
+        SignatureVerifier::new(&try!(read_file(path)), subject)
+    }
+
+    /// Use a local PEM certificate chain, leaf first, instead of downloading
+    /// the `x5u` url.
+    pub fn with_chain_file<P>(mut self, path: P) -> Result<SignatureVerifier, KintoError>
+        where P: AsRef<Path>
+    {
+        self.chain = Some(try!(X509::stack_from_pem(&try!(read_file(path)))));
+        Ok(self)
+    }
+
+    /// Get the local certificate chain, or download it.
+    fn fetch_chain(&self,
+                   config: &KintoConfig,
+                   x5u: &str)
+                   -> Result<Vec<X509>, KintoError> {
+        if let Some(ref chain) = self.chain {
+            return Ok(chain.clone());
+        }
+
+        let mut response = try!(config.http_client().get(x5u).send());
+        let mut content = vec![];
+        try!(response.read_to_end(&mut content));
+        if !response.status.is_success() {
+            let body = String::from_utf8_lossy(&content);
+            return Err(KintoError::from_response(response.status, &body));
+        }
+        Ok(try!(X509::stack_from_pem(&content)))
+    }
+
+    /// Validate a chain, leaf first, up to the trusted root with OpenSSL, and
+    /// check that the leaf is a code signing certificate for the subject.
+    pub fn verify_chain(&self, chain: &[X509]) -> Result<(), KintoError> {
+        let leaf = match chain.first() {
+            Some(leaf) => leaf,
+            None => return Err(signature_error("empty certificate chain")),
+        };
+
+        let mut store = try!(X509StoreBuilder::new());
+        try!(store.add_cert(self.trusted_root.clone()));
+        let store = store.build();
+
+        let mut intermediates = try!(Stack::new());
+        for certificate in &chain[1..] {
+            try!(intermediates.push(certificate.clone()));
+        }
+
+        let mut context = try!(X509StoreContext::new());
+        let error = try!(context.init(&store, leaf, &intermediates, |context| {
+            if try!(context.verify_cert()) {
+                Ok(None)
+            } else {
+                Ok(Some(context.error().error_string().to_owned()))
+            }
+        }));
+        if let Some(error) = error {
+            let message = format!("invalid certificate chain: {}", error);
+            return Err(KintoError::SignatureError(message));
+        }
+
+        if !has_code_signing_usage(leaf) {
+            return Err(signature_error("not a code signing certificate"));
+        }
+        if !certificate_names(leaf).contains(&self.subject) {
+            return Err(signature_error("unexpected certificate subject"));
+        }
+        Ok(())
+    }
+
+    /// Verify the signature of a payload with the certificate chain of the
+    /// signature metadata.
+    pub fn verify(&self,
+                  config: &KintoConfig,
+                  payload: &[u8],
+                  metadata: &SignatureMetadata)
+                  -> Result<(), KintoError> {
+        if !metadata.mode.is_empty() && metadata.mode != "p384ecdsa" {
+            let message = format!("unsupported signature mode {}", metadata.mode);
+            return Err(KintoError::SignatureError(message));
+        }
+
+        let chain = try!(self.fetch_chain(config, &metadata.x5u));
+        try!(self.verify_chain(&chain));
+
+        let raw = try!(decode_signature(&metadata.signature));
+        if raw.len() != P384_SIGNATURE_LENGTH {
+            return Err(signature_error("invalid signature length"));
+        }
+        let half = P384_SIGNATURE_LENGTH / 2;
+        let r = try!(BigNum::from_slice(&raw[..half]));
+        let s = try!(BigNum::from_slice(&raw[half..]));
+        let signature = try!(EcdsaSig::from_private_components(r, s));
+
+        let mut data = SIGNATURE_PREFIX.to_vec();
+        data.extend_from_slice(payload);
+        let digest = try!(hash(MessageDigest::sha384(), &data));
+
+        let key = try!(try!(chain[0].public_key()).ec_key());
+        if !try!(signature.verify(&digest, &key)) {
+            return Err(signature_error("signature does not match the data"));
+        }
+        Ok(())
+    }
+}
+
+
+fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, KintoError> {
+    let mut content = vec![];
+    try!(try!(File::open(path)).read_to_end(&mut content));
+    Ok(content)
+}
+
+
+/// Decode a base64url signature, with or without padding.
+fn decode_signature(signature: &str) -> Result<Vec<u8>, KintoError> {
+    let mut encoded = signature.trim().replace('-', "+").replace('_', "/");
+    let padding = (4 - encoded.len() % 4) % 4;
+    for _ in 0..padding {
+        encoded.push('=');
+    }
+    Ok(try!(base64::decode_block(&encoded)))
+}
+
+
+/// Whether the certificate has the code signing extended key usage.
+///
+/// The `codesign` verification purpose is only available since OpenSSL 3.2.
+fn has_code_signing_usage(certificate: &X509Ref) -> bool {
+    unsafe {
+        let flags = ffi::X509_get_extension_flags(certificate.as_ptr());
+        let usage = ffi::X509_get_extended_key_usage(certificate.as_ptr());
+        flags & ffi::EXFLAG_XKUSAGE != 0 && usage & ffi::XKU_CODE_SIGN != 0
+    }
+}
+
+
+/// Get the DNS names and common names of a certificate.
+fn certificate_names(certificate: &X509) -> Vec<String> {
+    let mut names = vec![];
+    if let Some(alt_names) = certificate.subject_alt_names() {
+        for name in alt_names.iter() {
+            if let Some(dns) = name.dnsname() {
+                names.push(dns.to_owned());
+            }
+        }
+    }
+    for entry in certificate.subject_name().entries_by_nid(Nid::COMMONNAME) {
+        names.push(String::from_utf8_lossy(entry.data().as_slice()).into_owned());
+    }
+    names
+}
+
+
+#[cfg(test)]
+mod test_signature {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::base64;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::hash::{hash, MessageDigest};
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::{X509, X509Builder, X509NameBuilder};
+    use openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage};
+
+    use signature::{SignatureMetadata, SignatureVerifier};
+    use utils::collection_payload;
+    use utils::tests::setup_config;
+
+    const SUBJECT: &str = "test.content-signature.example.com";
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// Usage of the test certificates.
+    #[derive(Clone, Copy)]
+    enum Usage {
+        Ca,
+        CodeSigning,
+        ServerAuth,
+    }
+
+    fn certificate(name: &str,
+                   key: &PKey<Private>,
+                   issuer: Option<(&X509, &PKey<Private>)>,
+                   usage: Usage)
+                   -> X509 {
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        match usage {
+            Usage::Ca => {
+                let constraints = BasicConstraints::new().critical().ca().build();
+                builder.append_extension(constraints.unwrap()).unwrap();
+                let key_usage = KeyUsage::new().critical().key_cert_sign().build();
+                builder.append_extension(key_usage.unwrap()).unwrap();
+            }
+            Usage::CodeSigning | Usage::ServerAuth => {
+                let key_usage = KeyUsage::new().critical().digital_signature().build();
+                builder.append_extension(key_usage.unwrap()).unwrap();
+                let mut extended = ExtendedKeyUsage::new();
+                match usage {
+                    Usage::CodeSigning => extended.code_signing(),
+                    _ => extended.server_auth(),
+                };
+                builder.append_extension(extended.build().unwrap()).unwrap();
+            }
+        }
+        match issuer {
+            Some((issuer, issuer_key)) => {
+                builder.set_issuer_name(issuer.subject_name()).unwrap();
+                builder.sign(issuer_key, MessageDigest::sha384()).unwrap();
+            }
+            None => {
+                builder.set_issuer_name(&subject).unwrap();
+                builder.sign(key, MessageDigest::sha384()).unwrap();
+            }
+        }
+        builder.build()
+    }
+
+    /// Build a root, intermediate and leaf chain and sign the payload.
+    fn sign(payload: &str, name: &str) -> (X509, PathBuf, SignatureMetadata) {
+        sign_with(payload, name, Usage::Ca, Usage::CodeSigning)
+    }
+
+    fn sign_with(payload: &str,
+                 name: &str,
+                 intermediate_usage: Usage,
+                 leaf_usage: Usage)
+                 -> (X509, PathBuf, SignatureMetadata) {
+        let root_key = key();
+        let root = certificate("Test Root", &root_key, None, Usage::Ca);
+        let intermediate_key = key();
+        let intermediate = certificate("Test Intermediate",
+                                       &intermediate_key,
+                                       Some((&root, &root_key)),
+                                       intermediate_usage);
+        let leaf_key = key();
+        let leaf = certificate(SUBJECT,
+                               &leaf_key,
+                               Some((&intermediate, &intermediate_key)),
+                               leaf_usage);
+
+        let path = env::temp_dir().join(format!("kinto-http-test-chain-{}.pem", name));
+        let mut file = File::create(&path).unwrap();
+        for certificate in [&leaf, &intermediate, &root].iter() {
+            file.write_all(&certificate.to_pem().unwrap()).unwrap();
+        }
+
+        let mut data = b"Content-Signature:\x00".to_vec();
+        data.extend_from_slice(payload.as_bytes());
+        let digest = hash(MessageDigest::sha384(), &data).unwrap();
+        let signature = EcdsaSig::sign(&digest, &leaf_key.ec_key().unwrap()).unwrap();
+        let mut raw = signature.r().to_vec_padded(48).unwrap();
+        raw.extend(signature.s().to_vec_padded(48).unwrap());
+        let encoded = base64::encode_block(&raw)
+            .replace('+', "-")
+            .replace('/', "_");
+
+        let metadata = SignatureMetadata {
+            signature: encoded,
+            x5u: "https://example.com/chains/test.pem".to_owned(),
+            mode: "p384ecdsa".to_owned(),
+        };
+        (root, path, metadata)
+    }
+
+    fn verifier(root: &X509, chain: &PathBuf) -> SignatureVerifier {
+        SignatureVerifier::new(&root.to_pem().unwrap(), SUBJECT)
+            .unwrap()
+            .with_chain_file(chain)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signature_metadata_from_data() {
+        let data = json!({
+            "id": "meat",
+            "signature": {
+                "signature": "abc",
+                "x5u": "https://example.com/chain.pem",
+                "mode": "p384ecdsa",
+                "ref": "1x2y3z",
+            },
+        });
+        let metadata = SignatureMetadata::from_data(&data).unwrap();
+        assert_eq!(metadata.x5u, "https://example.com/chain.pem");
+        assert_eq!(SignatureMetadata::from_data(&json!({"id": "meat"})), None);
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let payload = collection_payload(&[json!({"id": "a"})], 42);
+        let (root, chain, metadata) = sign(&payload, "valid");
+        verifier(&root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_signature_of_canonical_json() {
+        // Payload as serialized by kinto-signer, in Python.
+        let signed = r#"{"data":[{"count":3,"id":"a","price":1.5e-07,"#.to_owned() +
+                     r#""title":"caf\u00e9"}],"last_modified":"42"}"#;
+        let (root, chain, metadata) = sign(&signed, "canonical");
+        let records = [json!({"id": "a", "title": "café", "price": 1.5e-7, "count": 3})];
+        let payload = collection_payload(&records, 42);
+        verifier(&root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tampered_data() {
+        let payload = collection_payload(&[json!({"id": "a"})], 42);
+        let (root, chain, metadata) = sign(&payload, "tampered");
+        let tampered = collection_payload(&[json!({"id": "a"})], 43);
+        verifier(&root, &chain)
+            .verify(&setup_config(), tampered.as_bytes(), &metadata)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_untrusted_root() {
+        let payload = collection_payload(&[], 42);
+        let (_, chain, metadata) = sign(&payload, "untrusted");
+        let other_root = certificate("Other Root", &key(), None, Usage::Ca);
+        verifier(&other_root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_unexpected_subject() {
+        let payload = collection_payload(&[], 42);
+        let (root, chain, metadata) = sign(&payload, "subject");
+        let subject = "other.content-signature.example.com";
+        SignatureVerifier::new(&root.to_pem().unwrap(), subject)
+            .unwrap()
+            .with_chain_file(chain)
+            .unwrap()
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_leaf_without_code_signing() {
+        let payload = collection_payload(&[], 42);
+        let (root, chain, metadata) =
+            sign_with(&payload, "server-auth", Usage::Ca, Usage::ServerAuth);
+        let error = verifier(&root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap_err();
+        assert!(format!("{:?}", error).contains("not a code signing certificate"));
+    }
+
+    #[test]
+    fn test_intermediate_without_ca() {
+        let payload = collection_payload(&[], 42);
+        let (root, chain, metadata) =
+            sign_with(&payload, "not-ca", Usage::CodeSigning, Usage::CodeSigning);
+        let error = verifier(&root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap_err();
+        assert!(format!("{:?}", error).contains("invalid certificate chain"));
+    }
+
+    #[test]
+    fn test_unsupported_mode() {
+        let payload = collection_payload(&[], 42);
+        let (root, chain, mut metadata) = sign(&payload, "mode");
+        metadata.mode = "rsa".to_owned();
+        verifier(&root, &chain)
+            .verify(&setup_config(), payload.as_bytes(), &metadata)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,494 @@
+use serde_json;
+use serde_json::Value;
+
+use KintoConfig;
+use bucket::Bucket;
+use collection::Collection;
+use error::KintoError;
+use group::Group;
+use server_info::{ServerInfo, ServerUser};
+
+
+/// Review status of a collection signed by the kinto-signer plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignerStatus {
+    WorkInProgress,
+    ToReview,
+    ToSign,
+    Signed,
+    ToRollback,
+    ToResign,
+    ToRefresh,
+}
+
+
+impl SignerStatus {
+    /// Get the value of the collection `status` field.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SignerStatus::WorkInProgress => "work-in-progress",
+            SignerStatus::ToReview => "to-review",
+            SignerStatus::ToSign => "to-sign",
+            SignerStatus::Signed => "signed",
+            SignerStatus::ToRollback => "to-rollback",
+            SignerStatus::ToResign => "to-resign",
+            SignerStatus::ToRefresh => "to-refresh",
+        }
+    }
+}
+
+
+/// Step of the review workflow, taken on the source collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignerAction {
+    /// Ask the reviewers to approve the changes, as an editor.
+    RequestReview,
+    /// Approve the changes under review, as a reviewer.
+    Approve,
+    /// Send the changes under review back to the editors, as a reviewer.
+    Decline,
+    /// Discard the changes made since the last signature.
+    Rollback,
+}
+
+
+impl SignerAction {
+    /// Get the status set on the source collection.
+    pub fn status(&self) -> SignerStatus {
+        match *self {
+            SignerAction::RequestReview => SignerStatus::ToReview,
+            SignerAction::Approve => SignerStatus::ToSign,
+            SignerAction::Decline => SignerStatus::WorkInProgress,
+            SignerAction::Rollback => SignerStatus::ToRollback,
+        }
+    }
+}
+
+
+/// Bucket, and collection if not bucket wide, of a signed resource.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignerLocation {
+    pub bucket: String,
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+
+/// Source, preview and destination of a signed resource.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignerResource {
+    /// Collection edited by the editors.
+    pub source: SignerLocation,
+    /// Collection signed for review, if the review is enabled.
+    #[serde(default)]
+    pub preview: Option<SignerLocation>,
+    /// Collection signed once approved.
+    pub destination: SignerLocation,
+    #[serde(default)]
+    pub editors_group: Option<String>,
+    #[serde(default)]
+    pub reviewers_group: Option<String>,
+    /// Whether changes are reviewed, overriding the capability setting.
+    #[serde(default)]
+    pub to_review_enabled: Option<bool>,
+}
+
+
+impl SignerResource {
+    /// Whether the collection is the source, preview or destination.
+    fn matches(&self, bucket: &str, collection: &str) -> bool {
+        let mut locations = vec![&self.source, &self.destination];
+        if let Some(ref preview) = self.preview {
+            locations.push(preview);
+        }
+        locations.iter().any(|location| {
+            location.bucket == bucket &&
+            location
+                .collection
+                .iter()
+                .all(|id| id == collection)
+        })
+    }
+
+    /// Resolve bucket wide locations for the given collection id.
+    fn resolve(mut self, collection: &str) -> SignerResource {
+        {
+            let mut locations = vec![&mut self.source, &mut self.destination];
+            if let Some(ref mut preview) = self.preview {
+                locations.push(preview);
+            }
+            for location in locations {
+                if location.collection.is_none() {
+                    location.collection = Some(collection.to_owned());
+                }
+            }
+        }
+        self
+    }
+}
+
+
+/// Configuration exposed by the `signer` server capability.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignerCapability {
+    /// Group names, where `{collection_id}` is replaced by the source id.
+    #[serde(default = "default_editors_group")]
+    pub editors_group: String,
+    #[serde(default = "default_reviewers_group")]
+    pub reviewers_group: String,
+    #[serde(default)]
+    pub to_review_enabled: bool,
+    #[serde(default)]
+    pub resources: Vec<SignerResource>,
+}
+
+
+fn default_editors_group() -> String {
+    "{collection_id}-editors".to_owned()
+}
+
+
+fn default_reviewers_group() -> String {
+    "{collection_id}-reviewers".to_owned()
+}
+
+
+impl SignerCapability {
+    /// Read the capability, if the server has the plugin enabled.
+    pub fn from_server_info(info: &ServerInfo) -> Option<SignerCapability> {
+        match info.capability("signer") {
+            Some(capability) => serde_json::from_value(capability.to_owned()).ok(),
+            None => None,
+        }
+    }
+
+    /// Find the signed resource of a source, preview or destination
+    /// collection.
+    pub fn resource(&self, bucket: &str, collection: &str) -> Option<SignerResource> {
+        self.resources
+            .iter()
+            .find(|resource| resource.matches(bucket, collection))
+            .map(|resource| resource.clone().resolve(collection))
+    }
+
+    /// Get the editors and reviewers group ids of a signed resource.
+    pub fn group_ids(&self, resource: &SignerResource) -> (String, String) {
+        let collection = resource.source.collection.clone().unwrap_or_default();
+        let editors = resource
+            .editors_group
+            .clone()
+            .unwrap_or_else(|| self.editors_group.clone());
+        let reviewers = resource
+            .reviewers_group
+            .clone()
+            .unwrap_or_else(|| self.reviewers_group.clone());
+        (editors.replace("{collection_id}", &collection),
+         reviewers.replace("{collection_id}", &collection))
+    }
+
+    /// Check that the user belongs to the group taking a review step, and
+    /// does not approve their own review request, given the current metadata
+    /// of the source collection.
+    ///
+    /// Groups are not checked without review. Status transitions are left to
+    /// the server.
+    pub fn check_action(&self,
+                        resource: &SignerResource,
+                        action: SignerAction,
+                        data: &Value,
+                        user: Option<&ServerUser>)
+                        -> Result<(), KintoError> {
+        if !resource.to_review_enabled.unwrap_or(self.to_review_enabled) {
+            return Ok(());
+        }
+
+        let (editors, reviewers) = self.group_ids(resource);
+        let principals = user.map_or(vec![], |user| user.principals.clone());
+        let bucket = &resource.source.bucket;
+        let is_member = |group: &str| {
+            principals.contains(&format!("/buckets/{}/groups/{}", bucket, group))
+        };
+
+        let groups = match action {
+            SignerAction::RequestReview => vec![editors],
+            SignerAction::Approve | SignerAction::Decline => vec![reviewers],
+            SignerAction::Rollback => vec![editors, reviewers],
+        };
+        if !groups.iter().any(|group| is_member(group)) {
+            let message = format!("User is not a member of {}", groups.join(" or "));
+            return Err(KintoError::SignerError(message));
+        }
+
+        let user_id = user.map(|user| user.id.as_str());
+        if action == SignerAction::Approve &&
+           data["last_review_request_by"].as_str() == user_id {
+            let message = "Editors can not approve their own changes".to_owned();
+            return Err(KintoError::SignerError(message));
+        }
+        Ok(())
+    }
+}
+
+
+/// Collections and groups involved in the review of a signed collection.
+#[derive(Debug, Clone)]
+pub struct SignedCollections {
+    pub source: Collection,
+    pub preview: Option<Collection>,
+    pub destination: Collection,
+    /// Group allowed to request reviews, in the source bucket.
+    pub editors: Group,
+    /// Group allowed to approve or decline reviews, in the source bucket.
+    pub reviewers: Group,
+}
+
+
+impl SignedCollections {
+    pub fn new(config: KintoConfig,
+               capability: &SignerCapability,
+               resource: &SignerResource)
+               -> SignedCollections {
+        let collection = |location: &SignerLocation| {
+            let id = location.collection.clone().unwrap_or_default();
+            Bucket::new_by_id(config.clone(), &location.bucket).collection(&id)
+        };
+        let source_bucket = Bucket::new_by_id(config.clone(), &resource.source.bucket);
+        let (editors, reviewers) = capability.group_ids(resource);
+
+        SignedCollections {
+            source: collection(&resource.source),
+            preview: resource.preview.as_ref().map(&collection),
+            destination: collection(&resource.destination),
+            editors: source_bucket.group(&editors),
+            reviewers: source_bucket.group(&reviewers),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_signer {
+    use serde_json;
+    use serde_json::Value;
+
+    use error::KintoError;
+    use resource::Resource;
+    use server_info::{ServerInfo, ServerUser};
+    use signer::{SignerAction, SignerStatus, SignerCapability, SignerResource,
+                 SignedCollections};
+    use utils::tests::setup_config;
+
+    fn server_info() -> ServerInfo {
+        serde_json::from_value(json!({
+            "capabilities": {
+                "signer": {
+                    "to_review_enabled": true,
+                    "editors_group": "{collection_id}-editors",
+                    "reviewers_group": "{collection_id}-reviewers",
+                    "resources": [{
+                        "source": {"bucket": "main-workspace", "collection": null},
+                        "preview": {"bucket": "main-preview", "collection": null},
+                        "destination": {"bucket": "main", "collection": null},
+                    }, {
+                        "source": {"bucket": "security-workspace", "collection": "certs"},
+                        "destination": {
+                            "bucket": "security",
+                            "collection": "certificates",
+                        },
+                        "reviewers_group": "security-reviewers",
+                    }, {
+                        "source": {"bucket": "staging", "collection": "fonts"},
+                        "destination": {"bucket": "blocklists", "collection": "fonts"},
+                        "to_review_enabled": false,
+                    }],
+                },
+            },
+        }))
+                .unwrap()
+    }
+
+    #[test]
+    fn test_status_values() {
+        let status: SignerStatus = serde_json::from_value(json!("to-review")).unwrap();
+        assert_eq!(status, SignerStatus::ToReview);
+        assert_eq!(serde_json::to_value(SignerStatus::WorkInProgress).unwrap(),
+                   json!(SignerStatus::WorkInProgress.as_str()));
+        assert!(serde_json::from_value::<SignerStatus>(json!("done")).is_err());
+    }
+
+    #[test]
+    fn test_bucket_wide_resource() {
+        let capability = SignerCapability::from_server_info(&server_info()).unwrap();
+        assert!(capability.to_review_enabled);
+
+        let resource = capability.resource("main-preview", "fonts").unwrap();
+        assert_eq!(resource.source.bucket, "main-workspace");
+        assert_eq!(resource.source.collection.clone().unwrap(), "fonts");
+        assert_eq!(resource.destination.collection.clone().unwrap(), "fonts");
+
+        let (editors, reviewers) = capability.group_ids(&resource);
+        assert_eq!(editors, "fonts-editors");
+        assert_eq!(reviewers, "fonts-reviewers");
+    }
+
+    #[test]
+    fn test_collection_resource() {
+        let capability = SignerCapability::from_server_info(&server_info()).unwrap();
+        let resource = capability.resource("security", "certificates").unwrap();
+        assert_eq!(resource.source.collection.clone().unwrap(), "certs");
+        assert_eq!(resource.preview, None);
+        assert_eq!(capability.group_ids(&resource).1, "security-reviewers");
+
+        assert_eq!(capability.resource("security", "other"), None);
+        assert_eq!(capability.resource("blocklists", "addons"), None);
+    }
+
+    #[test]
+    fn test_signed_collections() {
+        let capability = SignerCapability::from_server_info(&server_info()).unwrap();
+        let resource = capability.resource("main-workspace", "fonts").unwrap();
+        let signed = SignedCollections::new(setup_config(), &capability, &resource);
+
+        assert_eq!(signed.source.record_path().unwrap(),
+                   "/buckets/main-workspace/collections/fonts");
+        assert_eq!(signed.preview.unwrap().record_path().unwrap(),
+                   "/buckets/main-preview/collections/fonts");
+        assert_eq!(signed.destination.record_path().unwrap(),
+                   "/buckets/main/collections/fonts");
+        assert_eq!(signed.reviewers.record_path().unwrap(),
+                   "/buckets/main-workspace/groups/fonts-reviewers");
+    }
+
+    fn fonts() -> (SignerCapability, SignerResource) {
+        let capability = SignerCapability::from_server_info(&server_info()).unwrap();
+        let resource = capability.resource("main-workspace", "fonts").unwrap();
+        (capability, resource)
+    }
+
+    fn user(id: &str, group: &str) -> ServerUser {
+        ServerUser {
+            id: id.to_owned(),
+            principals: vec![format!("/buckets/main-workspace/groups/{}", group)],
+            bucket: None,
+        }
+    }
+
+    fn assert_signer_error(result: Result<(), KintoError>) {
+        match result {
+            Err(KintoError::SignerError(_)) => (),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_request_review() {
+        let (capability, resource) = fonts();
+        let editor = user("account:alice", "fonts-editors");
+        let reviewer = user("account:bob", "fonts-reviewers");
+        let check = |data: Value, user: &ServerUser| {
+            capability.check_action(&resource,
+                                    SignerAction::RequestReview,
+                                    &data,
+                                    Some(user))
+        };
+
+        check(json!({"status": "work-in-progress"}), &editor).unwrap();
+        // Source collections have no status before their first change
+        check(json!({}), &editor).unwrap();
+        assert_signer_error(check(json!({"status": "work-in-progress"}), &reviewer));
+    }
+
+    #[test]
+    fn test_check_approve() {
+        let (capability, resource) = fonts();
+        let editor = user("account:alice", "fonts-editors");
+        let reviewer = user("account:bob", "fonts-reviewers");
+        let check = |requested_by: &str, user: &ServerUser| {
+            let data = json!({"status": "to-review",
+                              "last_review_request_by": requested_by});
+            capability.check_action(&resource, SignerAction::Approve, &data, Some(user))
+        };
+
+        check("account:alice", &reviewer).unwrap();
+        assert_signer_error(check("account:alice", &editor));
+        assert_signer_error(check("account:bob", &reviewer));
+
+        // Without review, editors sign their own changes
+        let mut capability = capability.clone();
+        capability.to_review_enabled = false;
+        let data = json!({"status": "work-in-progress"});
+        capability
+            .check_action(&resource, SignerAction::Approve, &data, Some(&editor))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check_decline() {
+        let (capability, resource) = fonts();
+        let editor = user("account:alice", "fonts-editors");
+        let reviewer = user("account:bob", "fonts-reviewers");
+        let check = |user: Option<&ServerUser>| {
+            capability.check_action(&resource,
+                                    SignerAction::Decline,
+                                    &json!({"status": "to-review"}),
+                                    user)
+        };
+
+        check(Some(&reviewer)).unwrap();
+        assert_signer_error(check(Some(&editor)));
+        assert_signer_error(check(None));
+    }
+
+    #[test]
+    fn test_check_rollback() {
+        let (capability, resource) = fonts();
+        let editor = user("account:alice", "fonts-editors");
+        let reviewer = user("account:bob", "fonts-reviewers");
+        let outsider = user("account:carol", "fonts-readers");
+        let check = |status: &str, user: &ServerUser| {
+            capability.check_action(&resource,
+                                    SignerAction::Rollback,
+                                    &json!({"status": status}),
+                                    Some(user))
+        };
+
+        check("work-in-progress", &editor).unwrap();
+        check("to-review", &reviewer).unwrap();
+        check("signed", &editor).unwrap();
+        assert_signer_error(check("to-review", &outsider));
+    }
+
+    #[test]
+    fn test_resource_review_overrides() {
+        let mut capability = SignerCapability::from_server_info(&server_info()).unwrap();
+        let editor = user("account:alice", "fonts-editors");
+        let data = json!({"status": "work-in-progress"});
+
+        // Review disabled for the resource only
+        let resource = capability.resource("staging", "fonts").unwrap();
+        assert_eq!(resource.to_review_enabled, Some(false));
+        capability
+            .check_action(&resource, SignerAction::Approve, &data, Some(&editor))
+            .unwrap();
+
+        // Review enabled for the resource only
+        capability.to_review_enabled = false;
+        let mut resource = capability.resource("main-workspace", "fonts").unwrap();
+        assert_eq!(resource.to_review_enabled, None);
+        capability
+            .check_action(&resource, SignerAction::Approve, &data, Some(&editor))
+            .unwrap();
+        resource.to_review_enabled = Some(true);
+        assert_signer_error(capability.check_action(&resource,
+                                                    SignerAction::Approve,
+                                                    &data,
+                                                    Some(&editor)));
+    }
+
+    #[test]
+    fn test_no_signer_capability() {
+        let info = ServerInfo::default();
+        assert_eq!(SignerCapability::from_server_info(&info), None);
+    }
+}
@@ -0,0 +1,314 @@
+use std::marker::PhantomData;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde::ser::Error;
+use serde_json;
+use serde_json::Value;
+
+use error::KintoError;
+use resource::Resource;
+use collection::Collection;
+use record::{Record, RecordPermissions};
+
+
+/// Kinto metadata fields, kept apart from the user payload.
+const METADATA_FIELDS: [&str; 3] = ["id", "last_modified", "deleted"];
+
+
+/// Record whose data is converted from and to a serde type.
+#[derive(Debug, Clone)]
+pub struct TypedRecord<T> {
+    pub data: Option<T>,
+    pub permissions: RecordPermissions,
+    pub collection: Collection,
+    pub id: Option<String>,
+    pub last_modified: Option<u64>,
+    pub deleted: bool,
+}
+
+
+impl<T> TypedRecord<T>
+    where T: Serialize + DeserializeOwned + Clone
+{
+    /// Create a new typed record without an id.
+    pub fn new(collection: Collection) -> Self {
+        TypedRecord {
+            data: None,
+            permissions: RecordPermissions::default(),
+            collection,
+            id: None,
+            last_modified: None,
+            deleted: false,
+        }
+    }
+
+    /// Create a new typed record with an id.
+    pub fn new_by_id(collection: Collection, id: &str) -> Self {
+        let mut record = TypedRecord::new(collection);
+        record.id = Some(id.to_owned());
+        record
+    }
+
+    /// Create a typed record from an untyped one.
+    pub fn from_record(record: Record) -> Result<Self, KintoError> {
+        let mut typed = TypedRecord::new(record.collection.clone());
+        try!(typed.update_from_record(record));
+        Ok(typed)
+    }
+
+    /// Convert into an untyped record, metadata included in the data.
+    ///
+    /// Fails with `KintoError::JsonError` if the data is not serialized as a
+    /// JSON object.
+    pub fn to_record(&self) -> Result<Record, KintoError> {
+        let mut record = Record::new(self.collection.clone());
+        record.id = self.id.clone();
+        record.permissions = self.permissions.clone();
+
+        if let Some(ref data) = self.data {
+            let mut object = match try!(serde_json::to_value(data)) {
+                Value::Object(object) => object,
+                value => {
+                    let message = format!("Record data must be an object, got {}", value);
+                    return Err(serde_json::Error::custom(message).into());
+                }
+            };
+            if let Some(last_modified) = self.last_modified {
+                object.insert("last_modified".to_owned(), last_modified.into());
+            }
+            record.data = Value::Object(object).into();
+        }
+        Ok(record)
+    }
+
+    /// Update the typed record from an untyped one.
+    fn update_from_record(&mut self, record: Record) -> Result<(), KintoError> {
+        self.id = record.get_id();
+        self.permissions = record.permissions;
+
+        let mut value = record.data.unwrap_or(Value::Null);
+        self.last_modified = value["last_modified"].as_u64();
+        self.deleted = value["deleted"].as_bool().unwrap_or(false);
+
+        // Tombstones do not carry the record payload.
+        if self.deleted || value.is_null() {
+            self.data = None;
+            return Ok(());
+        }
+
+        if let Some(object) = value.as_object_mut() {
+            for field in METADATA_FIELDS.iter() {
+                object.remove(*field);
+            }
+        }
+        self.data = Some(try!(serde_json::from_value(value)));
+        Ok(())
+    }
+
+    /// Load record by id if exists.
+    pub fn load(&mut self) -> Result<(), KintoError> {
+        let mut record = try!(self.to_record());
+        try!(record.load());
+        self.update_from_record(record)
+    }
+
+    /// Set current record to the server (create or update).
+    pub fn set(&mut self) -> Result<(), KintoError> {
+        let mut record = try!(self.to_record());
+        try!(record.set());
+        self.update_from_record(record)
+    }
+
+    /// Create if not exists the current record.
+    pub fn create(&mut self) -> Result<(), KintoError> {
+        let mut record = try!(self.to_record());
+        try!(record.create());
+        self.update_from_record(record)
+    }
+
+    /// Update an existing record if exists with the current record.
+    pub fn update(&mut self) -> Result<(), KintoError> {
+        let mut record = try!(self.to_record());
+        try!(record.update());
+        self.update_from_record(record)
+    }
+
+    /// Delete the current record from the server if exists.
+    pub fn delete(&mut self) -> Result<(), KintoError> {
+        let mut record = try!(self.to_record());
+        try!(record.delete());
+        self.update_from_record(record)
+    }
+}
+
+
+/// Collection whose records are converted from and to a serde type.
+#[derive(Debug, Clone)]
+pub struct TypedCollection<T> {
+    pub collection: Collection,
+    record_type: PhantomData<T>,
+}
+
+
+impl<T> TypedCollection<T>
+    where T: Serialize + DeserializeOwned + Clone
+{
+    pub fn new(collection: Collection) -> Self {
+        TypedCollection {
+            collection,
+            record_type: PhantomData,
+        }
+    }
+
+    /// Get a typed record by id.
+    pub fn record(&self, id: &str) -> TypedRecord<T> {
+        TypedRecord::new_by_id(self.collection.clone(), id)
+    }
+
+    /// Create a new empty typed record with a generated id.
+    pub fn new_record(&self) -> TypedRecord<T> {
+        TypedRecord::new(self.collection.clone())
+    }
+
+    /// List all available records.
+    pub fn list_records(&self) -> Result<Vec<TypedRecord<T>>, KintoError> {
+        let mut records = vec![];
+        for record in try!(self.collection.list_records()) {
+            records.push(try!(TypedRecord::from_record(record)));
+        }
+        Ok(records)
+    }
+}
+
+
+#[cfg(test)]
+mod test_typed {
+    use error::KintoError;
+    use typed::TypedRecord;
+    use utils::tests::{setup_config, setup_collection};
+    use bucket::Bucket;
+    use record::Record;
+    use resource::Resource;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Steak {
+        name: String,
+        price: u32,
+    }
+
+    fn entrecote() -> Steak {
+        Steak {
+            name: "entrecote".to_owned(),
+            price: 20,
+        }
+    }
+
+    #[test]
+    fn test_non_object_data_to_record() {
+        let collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        let mut record: TypedRecord<Vec<Steak>> = TypedRecord::new(collection.clone());
+        record.data = Some(vec![entrecote()]);
+        record.last_modified = Some(12);
+        match record.to_record() {
+            Err(KintoError::JsonError(_)) => (),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let mut record: TypedRecord<Option<Steak>> = TypedRecord::new(collection);
+        record.data = Some(None);
+        assert!(record.to_record().is_err());
+    }
+
+    #[test]
+    fn test_typed_record_to_record() {
+        let collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        let mut record = TypedRecord::new_by_id(collection, "a");
+        record.data = Some(entrecote());
+        record.last_modified = Some(12);
+
+        let record = record.to_record().unwrap();
+        assert_eq!(record.data.unwrap(),
+                   json!({"name": "entrecote", "price": 20, "last_modified": 12}));
+    }
+
+    #[test]
+    fn test_create_typed_record() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut record = collection.typed::<Steak>().new_record();
+        record.data = entrecote().into();
+
+        record.create().unwrap();
+        assert!(record.id.is_some());
+        assert!(record.last_modified.is_some());
+        assert_eq!(record.data.unwrap(), entrecote());
+    }
+
+    #[test]
+    fn test_load_typed_record() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut record = collection.typed::<Steak>().record("entrecote");
+        record.data = entrecote().into();
+        record.set().unwrap();
+
+        let mut loaded = collection.typed::<Steak>().record("entrecote");
+        loaded.load().unwrap();
+        assert_eq!(loaded.data.unwrap(), entrecote());
+        assert_eq!(loaded.last_modified, record.last_modified);
+    }
+
+    #[test]
+    fn test_load_typed_record_fails_on_invalid_data() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut record = collection.record("entrecote");
+        record.data = json!({"name": 42}).into();
+        record.set().unwrap();
+
+        let mut loaded = collection.typed::<Steak>().record("entrecote");
+        loaded.load().unwrap_err();
+    }
+
+    #[test]
+    fn test_delete_typed_record() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        let mut record = collection.typed::<Steak>().record("entrecote");
+        record.data = entrecote().into();
+        record.set().unwrap();
+
+        record.delete().unwrap();
+        assert!(record.deleted);
+        assert_eq!(record.data, None);
+    }
+
+    #[test]
+    fn test_list_typed_records() {
+        let mut collection = setup_collection();
+        collection.create().unwrap();
+        for _ in 0..3 {
+            let mut record = collection.typed::<Steak>().new_record();
+            record.data = entrecote().into();
+            record.create().unwrap();
+        }
+
+        let records = collection.typed::<Steak>().list_records().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].data, Some(entrecote()));
+    }
+
+    #[test]
+    fn test_typed_record_from_record() {
+        let collection = Bucket::new_by_id(setup_config(), "food").collection("meat");
+        let mut record = Record::new(collection);
+        record.data = json!({"id": "a", "last_modified": 12, "name": "entrecote", "price": 20})
+            .into();
+
+        let typed: TypedRecord<Steak> = TypedRecord::from_record(record).unwrap();
+        assert_eq!(typed.id.unwrap(), "a");
+        assert_eq!(typed.last_modified, Some(12));
+        assert_eq!(typed.data.unwrap(), entrecote());
+    }
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 
 use hyper::header::EntityTag;
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_json::Value;
+use sha2::{Sha256, Digest};
 
 use response::ResponseWrapper;
 use resource::Resource;
@@ -19,6 +23,16 @@ pub fn unwrap_collection_records<T>(wrapper: &ResponseWrapper, object: &T) -> Ve
 }
 
 
+/// Get the permissions of a resource response.
+///
+/// Deletion responses do not contain permissions, defaults are used then.
+pub fn unwrap_permissions<T>(wrapper: &ResponseWrapper) -> T
+    where T: DeserializeOwned + Default
+{
+    serde_json::from_value(wrapper.body["permissions"].to_owned()).unwrap_or_default()
+}
+
+
 /// Transform an integer timestamp into an Etag header.
 pub fn timestamp_to_etag(timestamp: u64) -> Vec<EntityTag> {
     let quoted = format!("{}", timestamp);
@@ -26,6 +40,146 @@ pub fn timestamp_to_etag(timestamp: u64) -> Vec<EntityTag> {
 }
 
 
+/// Build the JSON pointer of a principal in a permission (e.g. "/permissions/read/bob").
+pub fn permission_pointer(permission: &str, principal: &str) -> String {
+    let escape = |s: &str| s.replace("~", "~0").replace("/", "~1");
+    format!("/permissions/{}/{}", escape(permission), escape(principal))
+}
+
+
+/// Hex encoded SHA-256 digest.
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+
+/// Serialize a value like Python `canonicaljson`, as used by Kinto to sign
+/// collections.
+///
+/// Object keys are sorted, there is no whitespace, non-ASCII characters are
+/// escaped as `\uXXXX` (with surrogate pairs) and floats follow Python `repr`.
+///
+/// Integers outside of the `i64` and `u64` ranges are parsed as floats by
+/// `serde_json`, so they are serialized as floats (e.g. `1e+20`) where Python
+/// keeps every digit: the checksum or signature of such records differs.
+pub fn canonical_json(value: &Value) -> String {
+    let mut output = String::new();
+    write_canonical_json(value, &mut output);
+    output
+}
+
+
+fn write_canonical_json(value: &Value, output: &mut String) {
+    match *value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(b) => output.push_str(if b { "true" } else { "false" }),
+        Value::Number(ref n) => {
+            match n.as_f64() {
+                Some(f) if n.is_f64() => output.push_str(&python_float(f)),
+                _ => output.push_str(&n.to_string()),
+            }
+        }
+        Value::String(ref s) => write_canonical_string(s, output),
+        Value::Array(ref items) => {
+            output.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                write_canonical_json(item, output);
+            }
+            output.push(']');
+        }
+        Value::Object(ref map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            output.push('{');
+            for (index, key) in keys.into_iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                write_canonical_string(key, output);
+                output.push(':');
+                write_canonical_json(&map[key], output);
+            }
+            output.push('}');
+        }
+    }
+}
+
+
+fn write_canonical_string(s: &str, output: &mut String) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{c}' => output.push_str("\\f"),
+            ' '..='~' => output.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units).iter() {
+                    output.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    output.push('"');
+}
+
+
+/// Format a float like Python `repr`: shortest round-trip digits, with an
+/// exponent below 1e-4 or from 1e16.
+fn python_float(f: f64) -> String {
+    // Rust gives the shortest round-trip digits in scientific notation.
+    let scientific = format!("{:e}", f.abs());
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+    let digits = mantissa.replace(".", "");
+    let sign = if f.is_sign_negative() { "-" } else { "" };
+
+    if !(-4..16).contains(&exponent) {
+        let exponent_sign = if exponent < 0 { "-" } else { "+" };
+        return format!("{}{}e{}{:02}", sign, mantissa, exponent_sign, exponent.abs());
+    }
+    if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        return format!("{}0.{}{}", sign, zeros, digits);
+    }
+    let integer_length = exponent as usize + 1;
+    if digits.len() <= integer_length {
+        let zeros = "0".repeat(integer_length - digits.len());
+        format!("{}{}{}.0", sign, digits, zeros)
+    } else {
+        let (integer, fraction) = digits.split_at(integer_length);
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+
+/// Serialize the records and timestamp of a collection like kinto-signer:
+/// tombstones are skipped and records are sorted by id.
+pub fn collection_payload(records: &[Value], timestamp: u64) -> String {
+    let mut records: Vec<&Value> = records
+        .iter()
+        .filter(|record| !record["deleted"].as_bool().unwrap_or(false))
+        .collect();
+    records.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+
+    canonical_json(&json!({
+        "data": records,
+        "last_modified": timestamp.to_string(),
+    }))
+}
+
+
 /// Split a path (e.g. "/buckets/food/collections/foo") into a resource name `HashMap`.
 pub fn extract_ids_from_path(path: &str) -> HashMap<String, Option<String>> {
 
@@ -51,6 +205,104 @@ pub fn extract_ids_from_path(path: &str) -> HashMap<String, Option<String>> {
 }
 
 
+#[cfg(test)]
+mod test_utils {
+    use hyper::header::Headers;
+    use hyper::status::StatusCode;
+    use serde_json;
+    use serde_json::Value;
+
+    use bucket::BucketPermissions;
+    use response::ResponseWrapper;
+    use utils::{permission_pointer, unwrap_permissions};
+    use utils::{canonical_json, collection_payload};
+    use utils::tests::setup_config;
+
+    #[test]
+    fn test_unwrap_permissions() {
+        let mut response = ResponseWrapper {
+            config: setup_config(),
+            path: "/buckets/food".to_owned(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: json!({"data": {"id": "food"}, "permissions": {"read": ["bob"]}}),
+        };
+        let permissions: BucketPermissions = unwrap_permissions(&response);
+        assert_eq!(permissions.read.unwrap(), vec!["bob"]);
+
+        response.body = json!({"data": {"id": "food", "deleted": true}});
+        let permissions: BucketPermissions = unwrap_permissions(&response);
+        assert_eq!(permissions.read, None);
+    }
+
+    #[test]
+    fn test_permission_pointer() {
+        assert_eq!(permission_pointer("read", "system.Everyone"),
+                   "/permissions/read/system.Everyone");
+        assert_eq!(permission_pointer("collection:create", "/buckets/a/groups/b"),
+                   "/permissions/collection:create/~1buckets~1a~1groups~1b");
+        assert_eq!(permission_pointer("write", "a~b"), "/permissions/write/a~0b");
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        // Expected values from Python `json.dumps(value, sort_keys=True,
+        // separators=(",", ":"))`, as done by `canonicaljson`.
+        let corpus = vec![(json!(null), r#"null"#),
+                          (json!(true), r#"true"#),
+                          (json!({"b": 1, "a": [1, 2, {"d": null, "c": false}]}),
+                           r#"{"a":[1,2,{"c":false,"d":null}],"b":1}"#),
+                          (json!({"é": 1, "e": 2, "z": 3}),
+                           r#"{"e":2,"z":3,"\u00e9":1}"#),
+                          (json!("café"), r#""caf\u00e9""#),
+                          (json!("\u{1f600}"), r#""\ud83d\ude00""#),
+                          (json!("tab\there \"q\" \\ \u{1}\u{7f}/"),
+                           r#""tab\there \"q\" \\ \u0001\u007f/""#),
+                          (json!(-42), r#"-42"#),
+                          (json!(18446744073709551615u64), r#"18446744073709551615"#)];
+        for (value, expected) in corpus {
+            assert_eq!(canonical_json(&value), expected);
+        }
+    }
+
+    #[test]
+    fn test_canonical_json_big_integers() {
+        let value: Value = serde_json::from_str("100000000000000000000").unwrap();
+        assert_eq!(canonical_json(&value), "1e+20");
+        let value: Value = serde_json::from_str("-9223372036854775808").unwrap();
+        assert_eq!(canonical_json(&value), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_canonical_json_floats() {
+        let corpus = vec![(1.0, "1.0"),
+                          (-0.0, "-0.0"),
+                          (0.1, "0.1"),
+                          (2.675, "2.675"),
+                          (0.0001, "0.0001"),
+                          (2.5e-5, "2.5e-05"),
+                          (1.5e-7, "1.5e-07"),
+                          (1e15, "1000000000000000.0"),
+                          (1e16, "1e+16"),
+                          (1e30, "1e+30"),
+                          (123456789012345678.0, "1.2345678901234568e+17")];
+        for (value, expected) in corpus {
+            assert_eq!(canonical_json(&json!(value)), expected);
+        }
+    }
+
+    #[test]
+    fn test_collection_payload() {
+        let records = vec![json!({"id": "b", "last_modified": 2}),
+                           json!({"id": "c", "deleted": true}),
+                           json!({"last_modified": 1, "id": "a", "title": "é"})];
+        let expected = r#"{"data":[{"id":"a","last_modified":1,"title":"\u00e9"},"#
+            .to_owned() + r#"{"id":"b","last_modified":2}],"last_modified":"42"}"#;
+        assert_eq!(collection_payload(&records, 42), expected);
+    }
+}
+
+
 #[cfg(test)]
 pub mod tests {
 
@@ -61,6 +313,7 @@ pub mod tests {
     use resource::Resource;
     use bucket::Bucket;
     use collection::Collection;
+    use group::Group;
     use record::Record;
 
     /// Create a config.
@@ -96,6 +349,13 @@ pub mod tests {
     }
 
 
+    pub fn setup_group() -> Group {
+        let client = setup_client();
+        client.bucket("food").set().unwrap();
+        client.bucket("food").group("editors")
+    }
+
+
     pub fn setup_record() -> Record {
         let client = setup_client();
         client.bucket("food").set().unwrap();